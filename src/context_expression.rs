@@ -0,0 +1,701 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+pub const MAX_EXPRESSION_DEPTH: usize = 64;
+
+// Context Expression language used by Experiment and VariantRule targeting, e.g.
+//   AND(IN(SITEID, 0, 77), IN(CHANNELID, 1, 5, 6), EQ(F90D, "TRUE"))
+//
+// Grammar:
+//   expression := AND(expression, ...) | OR(expression, ...) | NOT(expression)
+//               | IN(KEY, literal, ...) | EQ(KEY, literal)
+//   literal    := integer | "string"
+//
+// Operators are case sensitive. KEY is looked up in EvaluationContext::context_map.
+// Operators nest at most MAX_EXPRESSION_DEPTH deep, so parsing and evaluation stay within the stack.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expression {
+    And(Vec<Expression>),
+    Or(Vec<Expression>),
+    Not(Box<Expression>),
+    In { key: String, values: Vec<Literal> },
+    Eq { key: String, value: Literal },
+}
+
+// Literal value on the right hand side of IN / EQ
+#[derive(Debug, Clone, PartialEq)]
+pub enum Literal {
+    Integer(i64),
+    Text(String),
+}
+
+// Parse failure with the byte offset of the offending token in the input
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub position: usize,
+    pub message: String,
+}
+
+// The innermost clause which made an expression evaluate to false, with the observed context values
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClauseMismatch {
+    pub clause: String,
+    pub observed: Vec<(String, Option<String>)>,
+}
+
+impl Literal {
+    fn matches(&self, actual: &str) -> bool {
+        match self {
+            Literal::Integer(expected) => actual.trim().parse::<i64>() == Ok(*expected),
+            Literal::Text(expected) => actual == expected,
+        }
+    }
+}
+
+impl Expression {
+    pub fn parse(input: &str) -> Result<Expression, ParseError> {
+        let tokens = tokenize(input)?;
+        let mut parser = Parser {
+            tokens,
+            cursor: 0,
+            input_len: input.len(),
+            depth: 0,
+        };
+        let expression = parser.expression()?;
+        if let Some(token) = parser.peek() {
+            return Err(ParseError::new(
+                token.position,
+                format!("unexpected {} after end of expression", token.kind),
+            ));
+        }
+        Ok(expression)
+    }
+
+    pub fn matches(&self, context_map: &HashMap<String, String>) -> bool {
+        self.evaluate(context_map).is_ok()
+    }
+
+    // Evaluate against the context; on failure report the innermost clause that failed
+    pub fn evaluate(&self, context_map: &HashMap<String, String>) -> Result<(), ClauseMismatch> {
+        match self {
+            Expression::And(operands) => operands
+                .iter()
+                .try_for_each(|operand| operand.evaluate(context_map)),
+            Expression::Or(operands) => {
+                if operands.iter().any(|operand| operand.matches(context_map)) {
+                    Ok(())
+                } else {
+                    Err(self.mismatch(context_map))
+                }
+            }
+            Expression::Not(operand) => {
+                if operand.matches(context_map) {
+                    Err(self.mismatch(context_map))
+                } else {
+                    Ok(())
+                }
+            }
+            Expression::In { key, values } => match context_map.get(key) {
+                Some(actual) if values.iter().any(|value| value.matches(actual)) => Ok(()),
+                _ => Err(self.mismatch(context_map)),
+            },
+            Expression::Eq { key, value } => match context_map.get(key) {
+                Some(actual) if value.matches(actual) => Ok(()),
+                _ => Err(self.mismatch(context_map)),
+            },
+        }
+    }
+
+    // Context keys referenced by this expression, in order of first appearance
+    pub fn context_keys(&self) -> Vec<&str> {
+        let mut keys = vec![];
+        self.collect_keys(&mut keys);
+        keys
+    }
+
+    fn collect_keys<'a>(&'a self, keys: &mut Vec<&'a str>) {
+        match self {
+            Expression::And(operands) | Expression::Or(operands) => {
+                for operand in operands {
+                    operand.collect_keys(keys);
+                }
+            }
+            Expression::Not(operand) => operand.collect_keys(keys),
+            Expression::In { key, .. } | Expression::Eq { key, .. } => {
+                if !keys.contains(&key.as_str()) {
+                    keys.push(key);
+                }
+            }
+        }
+    }
+
+    // The same expression with AND / OR operands and IN values sorted and deduplicated, so
+    // expressions which differ only in operand order compare equal
+    pub fn normalized(&self) -> Expression {
+        fn sorted<T: fmt::Display + PartialEq>(mut items: Vec<T>) -> Vec<T> {
+            items.sort_by_cached_key(|item| item.to_string());
+            items.dedup();
+            items
+        }
+        match self {
+            Expression::And(operands) => Expression::And(sorted(
+                operands.iter().map(Expression::normalized).collect(),
+            )),
+            Expression::Or(operands) => Expression::Or(sorted(
+                operands.iter().map(Expression::normalized).collect(),
+            )),
+            Expression::Not(operand) => Expression::Not(Box::new(operand.normalized())),
+            Expression::In { key, values } => Expression::In {
+                key: key.clone(),
+                values: sorted(values.clone()),
+            },
+            Expression::Eq { .. } => self.clone(),
+        }
+    }
+
+    fn mismatch(&self, context_map: &HashMap<String, String>) -> ClauseMismatch {
+        ClauseMismatch {
+            clause: self.to_string(),
+            observed: self
+                .context_keys()
+                .into_iter()
+                .map(|key| (key.to_string(), context_map.get(key).cloned()))
+                .collect(),
+        }
+    }
+}
+
+impl FromStr for Expression {
+    type Err = ParseError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        Expression::parse(input)
+    }
+}
+
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn join<T: fmt::Display>(items: &[T]) -> String {
+            items
+                .iter()
+                .map(|item| item.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        }
+        match self {
+            Expression::And(operands) => write!(f, "AND({})", join(operands)),
+            Expression::Or(operands) => write!(f, "OR({})", join(operands)),
+            Expression::Not(operand) => write!(f, "NOT({})", operand),
+            Expression::In { key, values } => write!(f, "IN({}, {})", key, join(values)),
+            Expression::Eq { key, value } => write!(f, "EQ({}, {})", key, value),
+        }
+    }
+}
+
+impl fmt::Display for Literal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Literal::Integer(value) => write!(f, "{}", value),
+            Literal::Text(value) => {
+                write!(
+                    f,
+                    "\"{}\"",
+                    value.replace('\\', "\\\\").replace('"', "\\\"")
+                )
+            }
+        }
+    }
+}
+
+impl ParseError {
+    fn new(position: usize, message: String) -> Self {
+        ParseError { position, message }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at position {}", self.message, self.position)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl fmt::Display for ClauseMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let observed = self
+            .observed
+            .iter()
+            .map(|(key, value)| match value {
+                Some(value) => format!("{}={}", key, value),
+                None => format!("{}=<missing>", key),
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        write!(f, "{} failed with {}", self.clause, observed)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum TokenKind {
+    Identifier(String),
+    Integer(i64),
+    Text(String),
+    LeftParen,
+    RightParen,
+    Comma,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Token {
+    kind: TokenKind,
+    position: usize,
+}
+
+impl fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenKind::Identifier(name) => write!(f, "identifier {}", name),
+            TokenKind::Integer(value) => write!(f, "integer {}", value),
+            TokenKind::Text(value) => write!(f, "string \"{}\"", value),
+            TokenKind::LeftParen => write!(f, "'('"),
+            TokenKind::RightParen => write!(f, "')'"),
+            TokenKind::Comma => write!(f, "','"),
+        }
+    }
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, ParseError> {
+    let mut tokens = vec![];
+    let mut chars = input.char_indices().peekable();
+    while let Some(&(position, current)) = chars.peek() {
+        let kind = match current {
+            c if c.is_whitespace() => {
+                chars.next();
+                continue;
+            }
+            '(' => {
+                chars.next();
+                TokenKind::LeftParen
+            }
+            ')' => {
+                chars.next();
+                TokenKind::RightParen
+            }
+            ',' => {
+                chars.next();
+                TokenKind::Comma
+            }
+            '"' => {
+                chars.next();
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '"')) => break,
+                        Some((escape_position, '\\')) => match chars.next() {
+                            Some((_, escaped @ ('"' | '\\'))) => text.push(escaped),
+                            _ => {
+                                return Err(ParseError::new(
+                                    escape_position,
+                                    "invalid escape sequence in string literal".to_string(),
+                                ));
+                            }
+                        },
+                        Some((_, c)) => text.push(c),
+                        None => {
+                            return Err(ParseError::new(
+                                position,
+                                "unterminated string literal".to_string(),
+                            ));
+                        }
+                    }
+                }
+                TokenKind::Text(text)
+            }
+            c if c == '-' || c.is_ascii_digit() => {
+                let mut end = position;
+                while let Some(&(index, c)) = chars.peek() {
+                    if (index == position && c == '-') || c.is_ascii_digit() {
+                        end = index + c.len_utf8();
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                let literal = &input[position..end];
+                let value = literal.parse::<i64>().map_err(|_| {
+                    ParseError::new(position, format!("invalid integer literal {}", literal))
+                })?;
+                TokenKind::Integer(value)
+            }
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                let mut end = position;
+                while let Some(&(index, c)) = chars.peek() {
+                    if c.is_ascii_alphanumeric() || c == '_' {
+                        end = index + c.len_utf8();
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                TokenKind::Identifier(input[position..end].to_string())
+            }
+            c => {
+                return Err(ParseError::new(
+                    position,
+                    format!("unexpected character '{}'", c),
+                ));
+            }
+        };
+        tokens.push(Token { kind, position });
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    cursor: usize,
+    input_len: usize,
+    // Operators currently open
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.cursor)
+    }
+
+    fn next(&mut self, expected: &str) -> Result<Token, ParseError> {
+        match self.tokens.get(self.cursor) {
+            Some(token) => {
+                self.cursor += 1;
+                Ok(token.clone())
+            }
+            None => Err(ParseError::new(
+                self.input_len,
+                format!("expected {}, found end of input", expected),
+            )),
+        }
+    }
+
+    fn expect(&mut self, kind: TokenKind, expected: &str) -> Result<(), ParseError> {
+        let token = self.next(expected)?;
+        if token.kind == kind {
+            Ok(())
+        } else {
+            Err(ParseError::new(
+                token.position,
+                format!("expected {}, found {}", expected, token.kind),
+            ))
+        }
+    }
+
+    // Consume either ',' (returns true) or ')' (returns false)
+    fn separator(&mut self) -> Result<bool, ParseError> {
+        let token = self.next("',' or ')'")?;
+        match token.kind {
+            TokenKind::Comma => Ok(true),
+            TokenKind::RightParen => Ok(false),
+            other => Err(ParseError::new(
+                token.position,
+                format!("expected ',' or ')', found {}", other),
+            )),
+        }
+    }
+
+    fn expression(&mut self) -> Result<Expression, ParseError> {
+        if self.depth == MAX_EXPRESSION_DEPTH {
+            let position = self.peek().map_or(self.input_len, |token| token.position);
+            return Err(ParseError::new(
+                position,
+                format!(
+                    "operators nested deeper than {} levels",
+                    MAX_EXPRESSION_DEPTH
+                ),
+            ));
+        }
+        self.depth += 1;
+        let expression = self.operation();
+        self.depth -= 1;
+        expression
+    }
+
+    fn operation(&mut self) -> Result<Expression, ParseError> {
+        let token = self.next("an operator")?;
+        let operator = match token.kind {
+            TokenKind::Identifier(name) => name,
+            other => {
+                return Err(ParseError::new(
+                    token.position,
+                    format!("expected an operator, found {}", other),
+                ));
+            }
+        };
+        if !matches!(operator.as_str(), "AND" | "OR" | "NOT" | "IN" | "EQ") {
+            return Err(ParseError::new(
+                token.position,
+                format!("unknown operator {}", operator),
+            ));
+        }
+        self.expect(
+            TokenKind::LeftParen,
+            &format!("'(' after operator {}", operator),
+        )?;
+        match operator.as_str() {
+            "AND" | "OR" => {
+                let mut operands = vec![self.expression()?];
+                while self.separator()? {
+                    operands.push(self.expression()?);
+                }
+                Ok(if operator == "AND" {
+                    Expression::And(operands)
+                } else {
+                    Expression::Or(operands)
+                })
+            }
+            "NOT" => {
+                let operand = self.expression()?;
+                self.expect(TokenKind::RightParen, "')' after the single operand of NOT")?;
+                Ok(Expression::Not(Box::new(operand)))
+            }
+            _ => {
+                let key = self.key(&operator)?;
+                self.expect(
+                    TokenKind::Comma,
+                    &format!("',' after context key of {}", operator),
+                )?;
+                let mut values = vec![self.literal()?];
+                if operator == "EQ" {
+                    self.expect(TokenKind::RightParen, "')' after the value of EQ")?;
+                    return Ok(Expression::Eq {
+                        key,
+                        value: values.remove(0),
+                    });
+                }
+                while self.separator()? {
+                    values.push(self.literal()?);
+                }
+                Ok(Expression::In { key, values })
+            }
+        }
+    }
+
+    fn key(&mut self, operator: &str) -> Result<String, ParseError> {
+        let expected = format!("a context key as first argument of {}", operator);
+        let token = self.next(&expected)?;
+        match token.kind {
+            TokenKind::Identifier(name) => Ok(name),
+            other => Err(ParseError::new(
+                token.position,
+                format!("expected {}, found {}", expected, other),
+            )),
+        }
+    }
+
+    fn literal(&mut self) -> Result<Literal, ParseError> {
+        let token = self.next("an integer or string literal")?;
+        match token.kind {
+            TokenKind::Integer(value) => Ok(Literal::Integer(value)),
+            TokenKind::Text(value) => Ok(Literal::Text(value)),
+            other => Err(ParseError::new(
+                token.position,
+                format!("expected an integer or string literal, found {}", other),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn parse_nested_expression() {
+        let expression =
+            Expression::parse("AND(IN(SITEID, 0, 77), IN(CHANNELID, 1, 5, 6), EQ(F90D, \"TRUE\"))")
+                .unwrap();
+        assert_eq!(
+            expression,
+            Expression::And(vec![
+                Expression::In {
+                    key: "SITEID".to_string(),
+                    values: vec![Literal::Integer(0), Literal::Integer(77)],
+                },
+                Expression::In {
+                    key: "CHANNELID".to_string(),
+                    values: vec![
+                        Literal::Integer(1),
+                        Literal::Integer(5),
+                        Literal::Integer(6)
+                    ],
+                },
+                Expression::Eq {
+                    key: "F90D".to_string(),
+                    value: Literal::Text("TRUE".to_string()),
+                },
+            ])
+        );
+        assert_eq!(
+            expression.context_keys(),
+            vec!["SITEID", "CHANNELID", "F90D"]
+        );
+    }
+
+    #[test]
+    fn parse_rejects_deep_nesting() {
+        let nested = |depth: usize| "NOT(".repeat(depth - 1) + "EQ(A, 1)" + &")".repeat(depth - 1);
+        assert!(Expression::parse(&nested(MAX_EXPRESSION_DEPTH)).is_ok());
+        assert_eq!(
+            Expression::parse(&nested(MAX_EXPRESSION_DEPTH + 1)),
+            Err(ParseError::new(
+                4 * MAX_EXPRESSION_DEPTH,
+                "operators nested deeper than 64 levels".to_string()
+            ))
+        );
+        assert!(Expression::parse(&nested(100_000)).is_err());
+    }
+
+    #[test]
+    fn display_round_trip() {
+        let source = "OR(NOT(EQ(NAME, \"a \\\"b\\\"\")), IN(SITEID, -1, 3))";
+        let expression: Expression = source.parse().unwrap();
+        assert_eq!(expression.to_string(), source);
+        assert_eq!(
+            Expression::parse(&expression.to_string()).unwrap(),
+            expression
+        );
+    }
+
+    #[test]
+    fn normalized_ignores_operand_order() {
+        let expression =
+            Expression::parse("AND(IN(SITEID, 77, 0), OR(EQ(F90D, \"TRUE\"), IN(CHANNELID, 1)))")
+                .unwrap();
+        let reordered = Expression::parse(
+            "AND(OR(IN(CHANNELID, 1), EQ(F90D, \"TRUE\")), IN(SITEID, 0, 77, 0))",
+        )
+        .unwrap();
+        assert_ne!(expression, reordered);
+        assert_eq!(expression.normalized(), reordered.normalized());
+        assert_eq!(
+            expression.normalized().to_string(),
+            "AND(IN(SITEID, 0, 77), OR(EQ(F90D, \"TRUE\"), IN(CHANNELID, 1)))"
+        );
+        assert_ne!(
+            Expression::parse("NOT(IN(SITEID, 0))")
+                .unwrap()
+                .normalized(),
+            Expression::parse("IN(SITEID, 0)").unwrap().normalized()
+        );
+    }
+
+    #[test]
+    fn evaluate_reports_failing_clause() {
+        let expression =
+            Expression::parse("AND(IN(SITEID, 0, 77), IN(CHANNELID, 1, 5, 6), EQ(F90D, \"TRUE\"))")
+                .unwrap();
+        assert!(expression.matches(&context(&[
+            ("SITEID", "77"),
+            ("CHANNELID", "6"),
+            ("F90D", "TRUE")
+        ])));
+
+        let mismatch = expression
+            .evaluate(&context(&[
+                ("SITEID", "100"),
+                ("CHANNELID", "6"),
+                ("F90D", "TRUE"),
+            ]))
+            .unwrap_err();
+        assert_eq!(
+            mismatch.to_string(),
+            "IN(SITEID, 0, 77) failed with SITEID=100"
+        );
+
+        let mismatch = expression
+            .evaluate(&context(&[("SITEID", "0"), ("CHANNELID", "6")]))
+            .unwrap_err();
+        assert_eq!(
+            mismatch.to_string(),
+            "EQ(F90D, \"TRUE\") failed with F90D=<missing>"
+        );
+    }
+
+    #[test]
+    fn evaluate_or_and_not() {
+        let expression = Expression::parse("OR(EQ(A, 1), NOT(IN(B, \"x\", \"y\")))").unwrap();
+        assert!(expression.matches(&context(&[("A", "1"), ("B", "x")])));
+        assert!(expression.matches(&context(&[("A", "2"), ("B", "z")])));
+        let mismatch = expression
+            .evaluate(&context(&[("A", "2"), ("B", "y")]))
+            .unwrap_err();
+        assert_eq!(
+            mismatch.to_string(),
+            "OR(EQ(A, 1), NOT(IN(B, \"x\", \"y\"))) failed with A=2, B=y"
+        );
+        // Integer literals never match non numeric context values
+        assert!(
+            !Expression::parse("EQ(A, 1)")
+                .unwrap()
+                .matches(&context(&[("A", "one")]))
+        );
+    }
+
+    #[test]
+    fn parse_errors_are_positional() {
+        let cases = [
+            ("", "expected an operator, found end of input at position 0"),
+            ("FOO(A, 1)", "unknown operator FOO at position 0"),
+            (
+                "AND IN(A, 1)",
+                "expected '(' after operator AND, found identifier IN at position 4",
+            ),
+            (
+                "IN(A 1)",
+                "expected ',' after context key of IN, found integer 1 at position 5",
+            ),
+            (
+                "IN(1, 1)",
+                "expected a context key as first argument of IN, found integer 1 at position 3",
+            ),
+            (
+                "IN(A, B)",
+                "expected an integer or string literal, found identifier B at position 6",
+            ),
+            (
+                "EQ(A, 1, 2)",
+                "expected ')' after the value of EQ, found ',' at position 7",
+            ),
+            (
+                "NOT(EQ(A, 1), EQ(B, 2))",
+                "expected ')' after the single operand of NOT, found ',' at position 12",
+            ),
+            (
+                "AND(EQ(A, 1)",
+                "expected ',' or ')', found end of input at position 12",
+            ),
+            ("EQ(A, \"open)", "unterminated string literal at position 6"),
+            (
+                "EQ(A, 1) EQ(B, 2)",
+                "unexpected identifier EQ after end of expression at position 9",
+            ),
+            ("EQ(A, #)", "unexpected character '#' at position 6"),
+        ];
+        for (input, message) in cases {
+            assert_eq!(
+                Expression::parse(input).unwrap_err().to_string(),
+                message,
+                "{}",
+                input
+            );
+        }
+    }
+}
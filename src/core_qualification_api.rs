@@ -0,0 +1,400 @@
+use crate::core_qualification_dto::{
+    ErrorMode, EvaluationContext, QualificationResult, QualificationResultType,
+};
+use crate::core_qualification_error::QualificationError;
+use crate::core_qualification_lib::QualificationEngine;
+use crate::core_qualification_trace::DecisionTrace;
+use crate::ep_dto::Experiment;
+use std::collections::HashMap;
+
+// Intake of QualificationEngine::evaluate
+#[derive(Debug, Default, PartialEq)]
+pub struct QualificationRequest {
+    pub experiments: Vec<Experiment>,
+    pub context: HashMap<String, String>,
+    pub opt_in_variant_display_ids: Vec<String>,
+    pub error_mode: ErrorMode,
+    // Record a DecisionTrace of every Phase and Mapper in the response
+    pub trace: bool,
+}
+
+// Outcome of QualificationEngine::evaluate, one ExperimentResult per requested experiment in priority order
+#[derive(Debug, Default, PartialEq)]
+pub struct QualificationResponse {
+    pub experiment_results: Vec<ExperimentResult>,
+    // Outcome of every requested opt-in, by variant display id
+    pub opt_in_results: HashMap<String, QualificationResult>,
+    // Error which aborted the whole request
+    pub error: Option<QualificationError>,
+    // Set when the request asked for a trace
+    pub trace: Option<DecisionTrace>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExperimentResult {
+    pub experiment_id: i32,
+    pub name: String,
+    // The variant the user is Qualified for, if any
+    pub assigned_variant: Option<AssignedVariant>,
+    // Final result of every variant of the experiment, in declaration order;
+    // empty when the request was aborted by an error
+    pub variant_results: Vec<VariantResult>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AssignedVariant {
+    pub variant_id: i32,
+    pub variant_display_id: String,
+    pub name: String,
+    pub value: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VariantResult {
+    pub variant_id: i32,
+    pub name: String,
+    pub qualification_result: QualificationResult,
+}
+
+impl QualificationResponse {
+    // Stable numeric code of the aborting error, 0 when the request succeeded
+    pub fn error_code(&self) -> i32 {
+        self.error.as_ref().map_or(0, QualificationError::code)
+    }
+
+    pub fn experiment_result(&self, experiment_id: i32) -> Option<&ExperimentResult> {
+        self.experiment_results
+            .iter()
+            .find(|experiment_result| experiment_result.experiment_id == experiment_id)
+    }
+}
+
+impl QualificationEngine {
+    pub fn evaluate(&self, request: QualificationRequest) -> QualificationResponse {
+        let mut context = EvaluationContext {
+            experiment_list: request.experiments,
+            context_map: request.context,
+            opt_in_variant_display_ids: request.opt_in_variant_display_ids,
+            error_mode: request.error_mode,
+            ..Default::default()
+        };
+        if request.trace {
+            context.trace = Some(DecisionTrace::new(&context));
+        }
+        self.qualify(&mut context);
+        QualificationResponse::from(context)
+    }
+}
+
+impl From<EvaluationContext> for QualificationResponse {
+    fn from(mut context: EvaluationContext) -> Self {
+        let mut experiment_list = std::mem::take(&mut context.experiment_list);
+        experiment_list.sort_by_key(|experiment| experiment.priority);
+        let experiment_results = experiment_list
+            .iter()
+            .map(|experiment| {
+                let variant_results: Vec<_> = experiment
+                    .variants
+                    .iter()
+                    .filter_map(|variant| {
+                        let qualification_result = context
+                            .result
+                            .variant_result_map
+                            .remove(&variant.variant_id)?;
+                        Some(VariantResult {
+                            variant_id: variant.variant_id,
+                            name: variant.name.clone(),
+                            qualification_result,
+                        })
+                    })
+                    .collect();
+                let assigned_variant = experiment
+                    .variants
+                    .iter()
+                    .find(|variant| {
+                        variant_results.iter().any(|variant_result| {
+                            variant_result.variant_id == variant.variant_id
+                                && variant_result
+                                    .qualification_result
+                                    .qualification_result_type
+                                    == QualificationResultType::Qualified
+                        })
+                    })
+                    .map(|variant| AssignedVariant {
+                        variant_id: variant.variant_id,
+                        variant_display_id: variant.variant_display_id.clone(),
+                        name: variant.name.clone(),
+                        value: variant.value.clone(),
+                    });
+                ExperimentResult {
+                    experiment_id: experiment.experiment_id,
+                    name: experiment.name.clone(),
+                    assigned_variant,
+                    variant_results,
+                }
+            })
+            .collect();
+        QualificationResponse {
+            experiment_results,
+            opt_in_results: context.opt_in_result_map,
+            error: context.error,
+            trace: context.trace,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core_qualification_trace::TraceStepKind;
+    use crate::ep_dto::{Traffic, Variant};
+
+    fn size_experiment(experiment_id: i32, priority: i32) -> Experiment {
+        let variant = |variant_id: i32, name: &str, spectrum: String| Variant {
+            name: name.to_string(),
+            value: name.to_lowercase(),
+            variant_id,
+            variant_display_id: format!("{}", variant_id),
+            variant_flags: 0,
+            variant_mod: Traffic::try_from(spectrum).unwrap(),
+            whitelisted_uids: vec![format!("uid{}", variant_id)],
+            ramp: None,
+        };
+        Experiment {
+            name: format!("Size Experiment {}", experiment_id),
+            context_expression: "".to_string(),
+            hashing_constant: format!("SIZE{}", experiment_id),
+            experiment_id,
+            experiment_flags: 0,
+            variant_rules: vec![],
+            variants: vec![
+                variant(
+                    experiment_id * 10,
+                    "Small",
+                    "1".repeat(50) + &"0".repeat(50),
+                ),
+                variant(
+                    experiment_id * 10 + 1,
+                    "Large",
+                    "0".repeat(50) + &"1".repeat(50),
+                ),
+            ],
+            base_mod: Traffic::try_from("1".repeat(100)).unwrap(),
+            randomization_unit_key: "GUID".to_string(),
+            layer: None,
+            priority,
+            bucket_count: 100,
+            start_time: None,
+            end_time: None,
+            paused: false,
+        }
+    }
+
+    #[test]
+    fn evaluate_assigns_variants() {
+        let request = QualificationRequest {
+            experiments: vec![size_experiment(1, 1), size_experiment(2, 0)],
+            context: HashMap::from([
+                ("GUID".to_string(), "guid_1".to_string()),
+                ("UID".to_string(), "uid11".to_string()),
+            ]),
+            opt_in_variant_display_ids: vec!["20".to_string()],
+            ..Default::default()
+        };
+        let response = QualificationEngine::default().evaluate(request);
+        assert_eq!(response.error_code(), 0);
+        let experiment_ids: Vec<_> = response
+            .experiment_results
+            .iter()
+            .map(|experiment_result| experiment_result.experiment_id)
+            .collect();
+        assert_eq!(experiment_ids, vec![2, 1]);
+
+        let opted_in = response.experiment_result(2).unwrap();
+        assert_eq!(
+            opted_in.assigned_variant,
+            Some(AssignedVariant {
+                variant_id: 20,
+                variant_display_id: "20".to_string(),
+                name: "Small".to_string(),
+                value: "small".to_string(),
+            })
+        );
+        assert_eq!(opted_in.variant_results.len(), 2);
+        assert_eq!(
+            response.opt_in_results["20"].qualification_result_type,
+            QualificationResultType::Qualified
+        );
+
+        let whitelisted = response.experiment_result(1).unwrap();
+        assert_eq!(
+            whitelisted.assigned_variant.as_ref().unwrap().value,
+            "large"
+        );
+        assert_eq!(
+            whitelisted.variant_results[1].qualification_result,
+            QualificationResult::new(QualificationResultType::Qualified, "whitelisted uid")
+        );
+    }
+
+    #[test]
+    fn evaluate_caps_qualified_experiments() {
+        let request = QualificationRequest {
+            experiments: vec![size_experiment(1, 1), size_experiment(2, 0)],
+            context: HashMap::from([("GUID".to_string(), "guid_1".to_string())]),
+            ..Default::default()
+        };
+        let response = QualificationEngine::with_max_qualified_experiments(1).evaluate(request);
+        assert!(
+            response
+                .experiment_result(2)
+                .unwrap()
+                .assigned_variant
+                .is_some()
+        );
+        let capped = response.experiment_result(1).unwrap();
+        assert_eq!(capped.assigned_variant, None);
+        assert_eq!(
+            capped.variant_results[0]
+                .qualification_result
+                .qualification_result_reason,
+            "Priority cap of 1 qualified experiments exceeded"
+        );
+    }
+
+    #[test]
+    fn evaluate_isolates_failing_experiment() {
+        let mut session_experiment = size_experiment(1, 0);
+        session_experiment.randomization_unit_key = "SESSION_ID".to_string();
+        let experiments = || vec![session_experiment.clone(), size_experiment(2, 0)];
+        let context = HashMap::from([("GUID".to_string(), "guid_1".to_string())]);
+
+        let response = QualificationEngine::default().evaluate(QualificationRequest {
+            experiments: experiments(),
+            context: context.clone(),
+            ..Default::default()
+        });
+        assert_eq!(response.error_code(), 1);
+        assert!(
+            response
+                .experiment_result(2)
+                .unwrap()
+                .variant_results
+                .is_empty()
+        );
+
+        let response = QualificationEngine::default().evaluate(QualificationRequest {
+            experiments: experiments(),
+            context,
+            error_mode: ErrorMode::IsolateExperiment,
+            ..Default::default()
+        });
+        assert_eq!(response.error, None);
+        let failed = response.experiment_result(1).unwrap();
+        assert_eq!(failed.assigned_variant, None);
+        assert_eq!(
+            failed.variant_results[0].qualification_result,
+            QualificationResult::error(QualificationError::MissingContextKey {
+                experiment_id: 1,
+                key: "SESSION_ID".to_string(),
+            })
+        );
+        assert!(
+            response
+                .experiment_result(2)
+                .unwrap()
+                .assigned_variant
+                .is_some()
+        );
+    }
+
+    #[test]
+    fn evaluate_records_trace_on_request() {
+        let request = || QualificationRequest {
+            experiments: vec![size_experiment(1, 0)],
+            context: HashMap::from([("GUID".to_string(), "guid_1".to_string())]),
+            ..Default::default()
+        };
+        assert_eq!(
+            QualificationEngine::default().evaluate(request()).trace,
+            None
+        );
+
+        let response = QualificationEngine::default().evaluate(QualificationRequest {
+            trace: true,
+            ..request()
+        });
+        let trace = response.trace.unwrap();
+        let step_names: Vec<_> = trace.steps.iter().map(|step| step.name.as_str()).collect();
+        assert_eq!(
+            step_names,
+            vec![
+                "InitializationPhase",
+                "SchedulePhase",
+                "ValidationPhase",
+                "MappingPhase",
+                "OptInMapper",
+                "UidListMapper",
+                "VariantRuleMapper",
+                "VariantMapper",
+                "ContextPhase",
+                "CollisionResolvePhase",
+                "PrioritizationPhase",
+                "ResultPackagedPhase",
+            ]
+        );
+        let assigned_variant_id = response.experiment_results[0]
+            .assigned_variant
+            .as_ref()
+            .unwrap()
+            .variant_id;
+        let variant_mapper_step = &trace.steps[7];
+        assert_eq!(variant_mapper_step.kind, TraceStepKind::Mapper);
+        assert_eq!(variant_mapper_step.undecided_experiment_ids, vec![1]);
+        assert_eq!(variant_mapper_step.changes.len(), 1);
+        assert_eq!(
+            variant_mapper_step.changes[0].variant_id,
+            assigned_variant_id
+        );
+        assert_eq!(
+            variant_mapper_step.changes[0].after,
+            Some(QualificationResultType::Deferred)
+        );
+        let packaged_changes: Vec<_> = trace.steps[11]
+            .changes
+            .iter()
+            .map(|change| (change.variant_id, change.before, change.after))
+            .collect();
+        assert!(packaged_changes.contains(&(
+            assigned_variant_id,
+            Some(QualificationResultType::Deferred),
+            Some(QualificationResultType::Qualified)
+        )));
+        assert_eq!(trace.steps[11].undecided_experiment_ids, vec![1]);
+        assert_eq!(trace.steps.len(), 12);
+    }
+
+    #[test]
+    fn evaluate_traces_experiments_out_of_priority_order() {
+        let response = QualificationEngine::default().evaluate(QualificationRequest {
+            experiments: vec![size_experiment(1, 1), size_experiment(2, 0)],
+            context: HashMap::from([("GUID".to_string(), "guid_1".to_string())]),
+            trace: true,
+            ..Default::default()
+        });
+        let trace = response.trace.unwrap();
+        let prioritization_step = trace
+            .steps
+            .iter()
+            .find(|step| step.name == "PrioritizationPhase")
+            .unwrap();
+        assert_eq!(prioritization_step.changes, vec![]);
+        let packaged_step = trace.steps.last().unwrap();
+        assert_eq!(packaged_step.name, "ResultPackagedPhase");
+        assert_eq!(packaged_step.changes.len(), 4);
+        for change in &packaged_step.changes {
+            assert_eq!(change.variant_id / 10, change.experiment_id);
+            assert_ne!(change.before, change.after);
+        }
+    }
+}
@@ -3,6 +3,8 @@
 // An attribute to hide warnings for unused imports.
 #![allow(unused_imports)]
 
+use crate::core_qualification_error::QualificationError;
+use crate::core_qualification_trace::DecisionTrace;
 use crate::ep_dto::Experiment;
 use std::collections::HashMap;
 
@@ -13,13 +15,22 @@ pub struct EvaluationContext {
     pub experiment_list: Vec<Experiment>,
     pub context_map: HashMap<String, String>,
     pub opt_in_variant_display_ids: Vec<String>,
+    pub error_mode: ErrorMode,
+    // Milliseconds since the Unix epoch, read once per request from the engine's Clock
+    pub now_millis: u64,
 
     // Output
-    pub error_code: i32,
-    pub error_message: String,
+    // Error which aborted the whole request
+    pub error: Option<QualificationError>,
     pub result_by_mapper: HashMap<String, EvaluationResult>,
     pub result_by_phase: HashMap<String, EvaluationResult>,
     pub result: EvaluationResult,
+    // Outcome of every requested opt-in, by variant display id
+    pub opt_in_result_map: HashMap<String, QualificationResult>,
+    // rule_id of the VariantRule which supplied the spectrums, by experiment_id
+    pub matched_rule_id_map: HashMap<i32, i32>,
+    // Set to Some before qualifying to record every Phase and Mapper decision
+    pub trace: Option<DecisionTrace>,
 }
 
 impl Default for EvaluationContext {
@@ -28,31 +39,161 @@ impl Default for EvaluationContext {
             experiment_list: vec![],
             context_map: HashMap::new(),
             opt_in_variant_display_ids: vec![],
-            error_code: 0,
-            error_message: "".to_string(),
+            error_mode: ErrorMode::default(),
+            now_millis: 0,
+            error: None,
             result_by_mapper: HashMap::new(),
             result_by_phase: HashMap::new(),
             result: EvaluationResult {
                 variant_result_map: HashMap::new(),
             },
+            opt_in_result_map: HashMap::new(),
+            matched_rule_id_map: HashMap::new(),
+            trace: None,
         }
     }
 }
 
+impl EvaluationContext {
+    // Record a Phase's decision on a variant in the final result and under the Phase's own name
+    pub fn record_phase_result(
+        &mut self,
+        phase_name: &str,
+        variant_id: i32,
+        qualification_result: QualificationResult,
+    ) {
+        self.result_by_phase
+            .entry(phase_name.to_string())
+            .or_default()
+            .variant_result_map
+            .insert(variant_id, qualification_result.clone());
+        self.result
+            .variant_result_map
+            .insert(variant_id, qualification_result);
+    }
+
+    // Record a Mapper's assignment of a variant under the Mapper's own name
+    pub fn record_mapper_result(
+        &mut self,
+        mapper_name: &str,
+        variant_id: i32,
+        qualification_result: QualificationResult,
+    ) {
+        self.result_by_mapper
+            .entry(mapper_name.to_string())
+            .or_default()
+            .variant_result_map
+            .insert(variant_id, qualification_result);
+    }
+
+    // An experiment is mapped once any Mapper recorded a result for one of its variants
+    pub fn is_experiment_mapped(&self, experiment: &Experiment) -> bool {
+        self.result_by_mapper.values().any(|evaluation_result| {
+            experiment.variants.iter().any(|variant| {
+                evaluation_result
+                    .variant_result_map
+                    .contains_key(&variant.variant_id)
+            })
+        })
+    }
+
+    // An experiment is forced once a Mapper qualified one of its variants outright (opt-in, whitelist)
+    pub fn is_experiment_forced(&self, experiment: &Experiment) -> bool {
+        self.result_by_mapper.values().any(|evaluation_result| {
+            experiment.variants.iter().any(|variant| {
+                evaluation_result
+                    .variant_result_map
+                    .get(&variant.variant_id)
+                    .is_some_and(|qualification_result| {
+                        qualification_result.qualification_result_type
+                            == QualificationResultType::Qualified
+                    })
+            })
+        })
+    }
+
+    // Variants of an undecided experiment that a Mapper assigned (Deferred or Qualified)
+    pub fn assigned_variant_ids(&self, experiment: &Experiment) -> Vec<i32> {
+        if self.is_experiment_decided(experiment) {
+            return vec![];
+        }
+        experiment
+            .variants
+            .iter()
+            .filter(|variant| {
+                self.result_by_mapper.values().any(|evaluation_result| {
+                    evaluation_result
+                        .variant_result_map
+                        .get(&variant.variant_id)
+                        .is_some_and(|qualification_result| {
+                            matches!(
+                                qualification_result.qualification_result_type,
+                                QualificationResultType::Deferred
+                                    | QualificationResultType::Qualified
+                            )
+                        })
+                })
+            })
+            .map(|variant| variant.variant_id)
+            .collect()
+    }
+
+    // An experiment is decided once any of its variants carries a final result
+    pub fn is_experiment_decided(&self, experiment: &Experiment) -> bool {
+        experiment.variants.iter().any(|variant| {
+            self.result
+                .variant_result_map
+                .contains_key(&variant.variant_id)
+        })
+    }
+}
+
+// How an error raised for one experiment affects the rest of the request
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ErrorMode {
+    #[default]
+    AbortRequest, // the first error is reported in EvaluationContext::error and the remaining Phases are skipped
+    IsolateExperiment, // the failing experiment's variants are Error and the other experiments carry on
+}
+
 // Evaluation Result by individual variant id
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Default, PartialEq)]
 pub struct EvaluationResult {
     pub variant_result_map: HashMap<i32, QualificationResult>,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct QualificationResult {
     pub qualification_result_type: QualificationResultType,
     pub qualification_result_reason: String,
+    // Set on Error results only
+    pub qualification_error: Option<QualificationError>,
+}
+
+impl QualificationResult {
+    pub fn new(
+        qualification_result_type: QualificationResultType,
+        qualification_result_reason: impl Into<String>,
+    ) -> Self {
+        QualificationResult {
+            qualification_result_type,
+            qualification_result_reason: qualification_result_reason.into(),
+            qualification_error: None,
+        }
+    }
+
+    pub fn error(qualification_error: QualificationError) -> Self {
+        QualificationResult {
+            qualification_result_type: QualificationResultType::Error,
+            qualification_result_reason: qualification_error.to_string(),
+            qualification_error: Some(qualification_error),
+        }
+    }
 }
 
 // Qualification Result by individual variant
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum QualificationResultType {
     Deferred,     // qualified at this stage and looking for next stage
     Qualified,    // final result is qualified
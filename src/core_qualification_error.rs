@@ -0,0 +1,174 @@
+use crate::context_expression::ParseError;
+use std::fmt;
+
+// Errors raised while qualifying, carrying the offending experiment / variant / rule ids.
+// Every variant has a stable numeric code (see code()) which must never be renumbered.
+#[derive(Debug, Clone, PartialEq)]
+pub enum QualificationError {
+    // The randomization_unit_key of an experiment is absent from the context
+    MissingContextKey {
+        experiment_id: i32,
+        key: String,
+    },
+    // An Experiment (rule_id None) or VariantRule context_expression does not parse
+    MalformedExpression {
+        experiment_id: i32,
+        rule_id: Option<i32>,
+        parse_error: ParseError,
+    },
+    // base_mod (variant_id None) or a variant spectrum, optionally of a VariantRule target, is unusable
+    InvalidSpectrum {
+        experiment_id: i32,
+        variant_id: Option<i32>,
+        rule_id: Option<i32>,
+        message: String,
+    },
+    // An opt-in display id matching no variant
+    UnknownDisplayId {
+        display_id: String,
+    },
+    // An opt-in display id matching several variants
+    AmbiguousDisplayId {
+        display_id: String,
+    },
+    // Several opt-in display ids selecting variants of the same experiment
+    ConflictingDisplayIds {
+        experiment_id: i32,
+        display_ids: Vec<String>,
+    },
+}
+
+impl QualificationError {
+    pub fn code(&self) -> i32 {
+        match self {
+            QualificationError::MissingContextKey { .. } => 1,
+            QualificationError::MalformedExpression { .. } => 2,
+            QualificationError::InvalidSpectrum { .. } => 3,
+            QualificationError::UnknownDisplayId { .. } => 4,
+            QualificationError::AmbiguousDisplayId { .. } => 5,
+            QualificationError::ConflictingDisplayIds { .. } => 6,
+        }
+    }
+
+    pub fn experiment_id(&self) -> Option<i32> {
+        match self {
+            QualificationError::MissingContextKey { experiment_id, .. }
+            | QualificationError::MalformedExpression { experiment_id, .. }
+            | QualificationError::InvalidSpectrum { experiment_id, .. }
+            | QualificationError::ConflictingDisplayIds { experiment_id, .. } => {
+                Some(*experiment_id)
+            }
+            QualificationError::UnknownDisplayId { .. }
+            | QualificationError::AmbiguousDisplayId { .. } => None,
+        }
+    }
+}
+
+impl fmt::Display for QualificationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QualificationError::MissingContextKey { key, .. } => {
+                write!(f, "Missing context key {}", key)
+            }
+            QualificationError::MalformedExpression {
+                rule_id: None,
+                parse_error,
+                ..
+            } => write!(f, "Malformed context expression: {}", parse_error),
+            QualificationError::MalformedExpression {
+                rule_id: Some(rule_id),
+                parse_error,
+                ..
+            } => write!(
+                f,
+                "Malformed context expression of rule_id {}: {}",
+                rule_id, parse_error
+            ),
+            QualificationError::InvalidSpectrum {
+                variant_id,
+                rule_id,
+                message,
+                ..
+            } => {
+                write!(f, "Invalid spectrum")?;
+                if let Some(rule_id) = rule_id {
+                    write!(f, " of rule_id {}", rule_id)?;
+                }
+                match variant_id {
+                    Some(variant_id) => write!(f, " for variant {}: {}", variant_id, message),
+                    None => write!(f, " for base_mod: {}", message),
+                }
+            }
+            QualificationError::UnknownDisplayId { display_id } => {
+                write!(f, "Unknown variant display id {}", display_id)
+            }
+            QualificationError::AmbiguousDisplayId { display_id } => {
+                write!(f, "Ambiguous variant display id {}", display_id)
+            }
+            QualificationError::ConflictingDisplayIds {
+                experiment_id,
+                display_ids,
+            } => write!(
+                f,
+                "Conflicting opt-in variant display ids {} for experiment {}",
+                display_ids.join(", "),
+                experiment_id
+            ),
+        }
+    }
+}
+
+impl std::error::Error for QualificationError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codes_are_stable() {
+        let parse_error = ParseError {
+            position: 3,
+            message: "unknown operator FOO".to_string(),
+        };
+        let errors = [
+            QualificationError::MissingContextKey {
+                experiment_id: 1,
+                key: "LOOKUP_ID".to_string(),
+            },
+            QualificationError::MalformedExpression {
+                experiment_id: 1,
+                rule_id: Some(4),
+                parse_error,
+            },
+            QualificationError::InvalidSpectrum {
+                experiment_id: 1,
+                variant_id: Some(10),
+                rule_id: None,
+                message: "spectrum must be 100 characters, found 99".to_string(),
+            },
+            QualificationError::UnknownDisplayId {
+                display_id: "zzzz".to_string(),
+            },
+            QualificationError::AmbiguousDisplayId {
+                display_id: "0aX0".to_string(),
+            },
+            QualificationError::ConflictingDisplayIds {
+                experiment_id: 1,
+                display_ids: vec!["0aX0".to_string(), "0aX1".to_string()],
+            },
+        ];
+        let codes: Vec<_> = errors.iter().map(QualificationError::code).collect();
+        assert_eq!(codes, vec![1, 2, 3, 4, 5, 6]);
+        assert_eq!(errors[0].to_string(), "Missing context key LOOKUP_ID");
+        assert_eq!(
+            errors[1].to_string(),
+            "Malformed context expression of rule_id 4: unknown operator FOO at position 3"
+        );
+        assert_eq!(
+            errors[2].to_string(),
+            "Invalid spectrum for variant 10: spectrum must be 100 characters, found 99"
+        );
+        assert_eq!(errors[2].experiment_id(), Some(1));
+        assert_eq!(errors[3].experiment_id(), None);
+    }
+}
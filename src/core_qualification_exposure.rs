@@ -0,0 +1,299 @@
+use crate::core_qualification_dto::{EvaluationContext, QualificationResultType};
+use crate::core_qualification_lib::{Phase, QualificationEngine};
+use std::sync::{Arc, Mutex};
+
+// A user being served a variant, emitted for every Qualified variant of a request
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ExposureEvent {
+    pub experiment_id: i32,
+    pub variant_id: i32,
+    pub randomization_unit_value: String,
+    // Milliseconds since the Unix epoch
+    pub timestamp: u64,
+    // The VariantRule which supplied the spectrums, if any
+    pub rule_id: Option<i32>,
+}
+
+// Destination of exposure events, e.g. an analytics pipeline.
+// Ensure: Never panic; failures are logged and the events dropped.
+pub trait ExposureSink {
+    fn emit(&self, event: ExposureEvent);
+
+    // Hand over buffered events, if the sink buffers any
+    fn flush(&self) {}
+}
+
+// Emits an ExposureEvent per Qualified variant once the ResultPackagedPhase has run, then
+// flushes the sink so no event of the request stays buffered.
+// Requests aborted by an error are not emitted.
+pub struct ExposurePhase {
+    pub sink: Arc<dyn ExposureSink>,
+}
+
+#[allow(unused_variables)]
+impl Phase for ExposurePhase {
+    fn before(&self, context: &mut EvaluationContext) {}
+
+    fn execute(&self, context: &mut EvaluationContext) {
+        for experiment in &context.experiment_list {
+            for variant in &experiment.variants {
+                let qualified = context
+                    .result
+                    .variant_result_map
+                    .get(&variant.variant_id)
+                    .is_some_and(|qualification_result| {
+                        qualification_result.qualification_result_type
+                            == QualificationResultType::Qualified
+                    });
+                if !qualified {
+                    continue;
+                }
+                self.sink.emit(ExposureEvent {
+                    experiment_id: experiment.experiment_id,
+                    variant_id: variant.variant_id,
+                    randomization_unit_value: context
+                        .context_map
+                        .get(&experiment.randomization_unit_key)
+                        .cloned()
+                        .unwrap_or_default(),
+                    timestamp: context.now_millis,
+                    rule_id: context
+                        .matched_rule_id_map
+                        .get(&experiment.experiment_id)
+                        .copied(),
+                });
+            }
+        }
+        self.sink.flush();
+    }
+
+    fn after(&self, context: &mut EvaluationContext) {
+        log::debug!("#ExposurePhase finished.");
+    }
+}
+
+impl QualificationEngine {
+    // Emit the exposures of every request to the sink, after the ResultPackagedPhase
+    pub fn with_exposure_sink(mut self, sink: Arc<dyn ExposureSink>) -> Self {
+        self.phases.push(Box::new(ExposurePhase { sink }));
+        self
+    }
+}
+
+// Keeps every event, for tests and for callers forwarding exposures themselves
+#[derive(Debug, Default)]
+pub struct InMemoryExposureSink {
+    events: Mutex<Vec<ExposureEvent>>,
+}
+
+impl InMemoryExposureSink {
+    pub fn events(&self) -> Vec<ExposureEvent> {
+        self.events
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    pub fn take_events(&self) -> Vec<ExposureEvent> {
+        std::mem::take(&mut *self.events.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+}
+
+impl ExposureSink for InMemoryExposureSink {
+    fn emit(&self, event: ExposureEvent) {
+        self.events
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(event);
+    }
+}
+
+// Appends one JSON object per line to a file
+#[cfg(feature = "serde")]
+pub struct NdjsonFileExposureSink {
+    path: std::path::PathBuf,
+    writer: Mutex<std::io::BufWriter<std::fs::File>>,
+}
+
+#[cfg(feature = "serde")]
+impl NdjsonFileExposureSink {
+    pub fn create(path: impl AsRef<std::path::Path>) -> std::io::Result<Self> {
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)?;
+        Ok(NdjsonFileExposureSink {
+            path: path.as_ref().to_path_buf(),
+            writer: Mutex::new(std::io::BufWriter::new(file)),
+        })
+    }
+}
+
+#[cfg(feature = "serde")]
+impl ExposureSink for NdjsonFileExposureSink {
+    fn emit(&self, event: ExposureEvent) {
+        use std::io::Write;
+        let mut writer = self.writer.lock().unwrap_or_else(|e| e.into_inner());
+        let written = serde_json::to_writer(&mut *writer, &event)
+            .map_err(std::io::Error::from)
+            .and_then(|()| writer.write_all(b"\n"));
+        if let Err(io_error) = written {
+            log::error!(
+                "Cannot write exposure to {}: {}",
+                self.path.display(),
+                io_error
+            );
+        }
+    }
+
+    fn flush(&self) {
+        use std::io::Write;
+        let mut writer = self.writer.lock().unwrap_or_else(|e| e.into_inner());
+        if let Err(io_error) = writer.flush() {
+            log::error!(
+                "Cannot flush exposures to {}: {}",
+                self.path.display(),
+                io_error
+            );
+        }
+    }
+}
+
+// Buffers events and hands them to the inner sink batch_size at a time, then flushes it.
+// The remaining events are handed over on flush, which an ExposurePhase calls after every request,
+// and when the sink is dropped.
+pub struct BatchingExposureSink<S: ExposureSink> {
+    inner: S,
+    batch_size: usize,
+    buffer: Mutex<Vec<ExposureEvent>>,
+}
+
+impl<S: ExposureSink> BatchingExposureSink<S> {
+    pub fn new(inner: S, batch_size: usize) -> Self {
+        BatchingExposureSink {
+            inner,
+            batch_size: batch_size.max(1),
+            buffer: Mutex::new(vec![]),
+        }
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    fn hand_over(&self, batch: Vec<ExposureEvent>) {
+        if batch.is_empty() {
+            return;
+        }
+        batch.into_iter().for_each(|event| self.inner.emit(event));
+        self.inner.flush();
+    }
+}
+
+impl<S: ExposureSink> ExposureSink for BatchingExposureSink<S> {
+    fn emit(&self, event: ExposureEvent) {
+        let batch = {
+            let mut buffer = self.buffer.lock().unwrap_or_else(|e| e.into_inner());
+            buffer.push(event);
+            if buffer.len() < self.batch_size {
+                return;
+            }
+            std::mem::take(&mut *buffer)
+        };
+        self.hand_over(batch);
+    }
+
+    fn flush(&self) {
+        let batch = std::mem::take(&mut *self.buffer.lock().unwrap_or_else(|e| e.into_inner()));
+        self.hand_over(batch);
+    }
+}
+
+impl<S: ExposureSink> Drop for BatchingExposureSink<S> {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(variant_id: i32) -> ExposureEvent {
+        ExposureEvent {
+            experiment_id: 1,
+            variant_id,
+            randomization_unit_value: "guid_1".to_string(),
+            timestamp: 1_700_000_000_000,
+            rule_id: None,
+        }
+    }
+
+    // Counts the flushes of an in-memory sink
+    #[derive(Default)]
+    struct FlushCountingSink {
+        events: InMemoryExposureSink,
+        flushes: Mutex<usize>,
+    }
+
+    impl ExposureSink for FlushCountingSink {
+        fn emit(&self, event: ExposureEvent) {
+            self.events.emit(event);
+        }
+
+        fn flush(&self) {
+            *self.flushes.lock().unwrap() += 1;
+        }
+    }
+
+    #[test]
+    fn batching_sink_hands_over_full_batches() {
+        let sink = BatchingExposureSink::new(FlushCountingSink::default(), 2);
+        sink.emit(event(10));
+        assert!(sink.inner().events.events().is_empty());
+        sink.emit(event(11));
+        sink.emit(event(12));
+        assert_eq!(sink.inner().events.events(), vec![event(10), event(11)]);
+        assert_eq!(*sink.inner().flushes.lock().unwrap(), 1);
+        sink.flush();
+        assert_eq!(sink.inner().events.take_events().len(), 3);
+        assert_eq!(*sink.inner().flushes.lock().unwrap(), 2);
+        sink.flush();
+        assert_eq!(*sink.inner().flushes.lock().unwrap(), 2);
+    }
+
+    #[test]
+    fn exposure_phase_flushes_its_sink() {
+        let sink = Arc::new(BatchingExposureSink::new(FlushCountingSink::default(), 10));
+        sink.emit(event(10));
+        let phase = ExposurePhase { sink: sink.clone() };
+        phase.execute(&mut EvaluationContext::default());
+        assert_eq!(sink.inner().events.events(), vec![event(10)]);
+        assert_eq!(*sink.inner().flushes.lock().unwrap(), 1);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn ndjson_sink_appends_lines() {
+        let path = std::env::temp_dir().join(format!(
+            "feature4qi-exposures-{}.ndjson",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let sink = NdjsonFileExposureSink::create(&path).unwrap();
+        sink.emit(event(10));
+        sink.emit(ExposureEvent {
+            rule_id: Some(3),
+            ..event(11)
+        });
+        sink.flush();
+        let written = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            written,
+            "{\"experiment_id\":1,\"variant_id\":10,\"randomization_unit_value\":\"guid_1\",\"timestamp\":1700000000000,\"rule_id\":null}\n\
+             {\"experiment_id\":1,\"variant_id\":11,\"randomization_unit_value\":\"guid_1\",\"timestamp\":1700000000000,\"rule_id\":3}\n"
+        );
+    }
+}
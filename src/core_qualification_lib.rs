@@ -7,13 +7,31 @@
 // An attribute to hide warnings for unused mutable.
 #![allow(unused_mut)]
 
-use crate::core_qualification_dto::EvaluationContext;
+use crate::clock::{Clock, SystemClock};
+use crate::context_expression::Expression;
+use crate::core_qualification_dto::{
+    ErrorMode, EvaluationContext, QualificationResult, QualificationResultType,
+};
+use crate::core_qualification_error::QualificationError;
+use crate::core_qualification_trace::TraceStepKind;
+use crate::ep_dto::{Experiment, Holdout, RampSchedule, Traffic, Variant};
+use crate::hashing::{BucketAssignment, assign_variant_with, bucket_of, holdout_salt};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+// Context key holding the user id matched against Variant::whitelisted_uids
+pub const UID_CONTEXT_KEY: &str = "UID";
 
 // Abstract different Phase for Core Qualification
 // Each Phase will execute these pre-defined methods following the accordingly sequence logically:
 // #before --> #execute --> #after
 // Ensure: Never throw ANY exception from any below methods
 pub trait Phase {
+    // Name of the Phase in decision traces, the type name by default
+    fn name(&self) -> &'static str {
+        short_type_name::<Self>()
+    }
+
     fn before(&self, context: &mut EvaluationContext);
 
     fn execute(&self, context: &mut EvaluationContext);
@@ -26,6 +44,11 @@ pub trait Phase {
 // #before --> #map --> #after
 // Ensure: Never throw ANY exception from any below methods
 pub trait Mapper {
+    // Name of the Mapper in decision traces, the type name by default
+    fn name(&self) -> &'static str {
+        short_type_name::<Self>()
+    }
+
     fn before(&self, context: &mut EvaluationContext);
 
     fn map(&self, context: &mut EvaluationContext);
@@ -33,6 +56,11 @@ pub trait Mapper {
     fn after(&self, context: &mut EvaluationContext);
 }
 
+fn short_type_name<T: ?Sized>() -> &'static str {
+    let type_name = std::any::type_name::<T>();
+    type_name.rsplit("::").next().unwrap_or(type_name)
+}
+
 pub struct InitializationPhase;
 
 impl Phase for InitializationPhase {
@@ -47,27 +75,79 @@ impl Phase for InitializationPhase {
     }
 }
 
+// Scheduling: every variant of a paused experiment, or of an experiment whose
+// [start_time, end_time) window does not hold EvaluationContext::now_millis, is NotQualified
+pub struct SchedulePhase;
+
+impl Phase for SchedulePhase {
+    fn before(&self, context: &mut EvaluationContext) {}
+
+    fn execute(&self, context: &mut EvaluationContext) {
+        let mut decisions = vec![];
+        for experiment in &context.experiment_list {
+            let reason = if experiment.paused {
+                "Not active: paused".to_string()
+            } else if let Some(start_time) = experiment
+                .start_time
+                .filter(|start_time| context.now_millis < *start_time)
+            {
+                format!("Not active: starts at {}", start_time)
+            } else if let Some(end_time) = experiment
+                .end_time
+                .filter(|end_time| context.now_millis >= *end_time)
+            {
+                format!("Not active: ended at {}", end_time)
+            } else {
+                continue;
+            };
+            for variant in &experiment.variants {
+                decisions.push((
+                    variant.variant_id,
+                    QualificationResult::new(QualificationResultType::NotQualified, reason.clone()),
+                ));
+            }
+        }
+        for (variant_id, qualification_result) in decisions {
+            context.record_phase_result("SchedulePhase", variant_id, qualification_result);
+        }
+    }
+
+    fn after(&self, context: &mut EvaluationContext) {
+        log::debug!("#SchedulePhase finished.");
+    }
+}
+
+// Every experiment needs its randomization_unit_key in the context. Depending on the ErrorMode
+// a missing key either aborts the request or only turns the experiment's variants into Error.
+// Experiments already decided (not active) need no context.
 pub struct ValidationPhase;
 
 impl Phase for ValidationPhase {
     fn before(&self, context: &mut EvaluationContext) {}
 
     fn execute(&self, context: &mut EvaluationContext) {
-        for experiment in &mut context.experiment_list {
+        let mut decisions = vec![];
+        for experiment in &context.experiment_list {
+            if context.is_experiment_decided(experiment) {
+                continue;
+            }
             let must_to_have_context_key = experiment.randomization_unit_key.to_string();
-            if !context
-                .context_map
-                .contains_key(&String::from(must_to_have_context_key))
-            {
-                context.error_code = 1;
-                context.error_message = format!(
-                    "Missing context key {}",
-                    experiment.randomization_unit_key.to_string()
-                );
-                log::error!("{}", context.error_message);
-                break;
+            if !context.context_map.contains_key(&must_to_have_context_key) {
+                let qualification_error = QualificationError::MissingContextKey {
+                    experiment_id: experiment.experiment_id,
+                    key: must_to_have_context_key,
+                };
+                if context.error_mode == ErrorMode::AbortRequest {
+                    log::error!("{}", qualification_error);
+                    context.error = Some(qualification_error);
+                    break;
+                }
+                decisions.extend(error_results(experiment, qualification_error));
             }
         }
+        for (variant_id, qualification_result) in decisions {
+            context.record_phase_result("ValidationPhase", variant_id, qualification_result);
+        }
     }
 
     fn after(&self, context: &mut EvaluationContext) {
@@ -75,6 +155,63 @@ impl Phase for ValidationPhase {
     }
 }
 
+// Holdouts: every variant of an experiment in the scope of a Holdout whose traffic holds the user's
+// bucket is NotQualified, before any Mapper can assign or force it. Invalid holdouts are rejected
+// by QualificationEngine::with_holdouts and ignored by a HoldoutPhase built by hand.
+pub struct HoldoutPhase {
+    pub holdouts: Vec<Holdout>,
+}
+
+impl Phase for HoldoutPhase {
+    fn before(&self, context: &mut EvaluationContext) {}
+
+    fn execute(&self, context: &mut EvaluationContext) {
+        let holding: Vec<_> = self
+            .holdouts
+            .iter()
+            .filter(|holdout| {
+                if let Err(message) = holdout.validate() {
+                    log::error!("Holdout {}: {}", holdout.holdout_id, message);
+                    return false;
+                }
+                context
+                    .context_map
+                    .get(&holdout.randomization_unit_key)
+                    .is_some_and(|unit_value| {
+                        holdout.traffic.contains_bucket(bucket_of(
+                            &holdout_salt(holdout),
+                            unit_value,
+                            holdout.bucket_count,
+                        ))
+                    })
+            })
+            .collect();
+        let mut decisions = vec![];
+        for experiment in &context.experiment_list {
+            if context.is_experiment_decided(experiment) {
+                continue;
+            }
+            let Some(holdout) = holding.iter().find(|holdout| holdout.covers(experiment)) else {
+                continue;
+            };
+            let qualification_result = QualificationResult::new(
+                QualificationResultType::NotQualified,
+                format!("holdout {}", holdout.name),
+            );
+            for variant in &experiment.variants {
+                decisions.push((variant.variant_id, qualification_result.clone()));
+            }
+        }
+        for (variant_id, qualification_result) in decisions {
+            context.record_phase_result("HoldoutPhase", variant_id, qualification_result);
+        }
+    }
+
+    fn after(&self, context: &mut EvaluationContext) {
+        log::debug!("#HoldoutPhase finished.");
+    }
+}
+
 pub struct MappingPhase {
     pub mappers: Vec<Box<dyn Mapper>>,
 }
@@ -84,9 +221,11 @@ impl Phase for MappingPhase {
 
     fn execute(&self, mut context: &mut EvaluationContext) {
         for individual_mapper in self.mappers.iter() {
-            individual_mapper.before(context);
-            individual_mapper.map(context);
-            individual_mapper.after(context);
+            context.traced(individual_mapper.name(), TraceStepKind::Mapper, |context| {
+                individual_mapper.before(context);
+                individual_mapper.map(context);
+                individual_mapper.after(context);
+            });
         }
     }
 
@@ -95,96 +234,630 @@ impl Phase for MappingPhase {
     }
 }
 
+// Opt-in: every requested variant display id is resolved across experiment_list and its variant is
+// Qualified for its experiment. Unknown or ambiguous display ids, and several display ids opting
+// into the same experiment, are reported as Error in opt_in_result_map.
 pub struct OptInMapper;
 
 impl Mapper for OptInMapper {
     fn before(&self, context: &mut EvaluationContext) {}
 
-    fn map(&self, context: &mut EvaluationContext) {}
+    fn map(&self, context: &mut EvaluationContext) {
+        let mut opt_in_results = vec![];
+        // experiment_id -> opted-in (display id, variant id), in request order
+        let mut opt_ins_by_experiment: Vec<(i32, Vec<(String, i32)>)> = vec![];
+        for display_id in &context.opt_in_variant_display_ids {
+            if opt_in_results.iter().any(|(id, _)| id == display_id) {
+                continue;
+            }
+            let matches: Vec<_> = context
+                .experiment_list
+                .iter()
+                .flat_map(|experiment| {
+                    experiment
+                        .variants
+                        .iter()
+                        .filter(|variant| &variant.variant_display_id == display_id)
+                        .map(move |variant| (experiment, variant))
+                })
+                .collect();
+            let (experiment, variant) = match matches.as_slice() {
+                [] => {
+                    opt_in_results.push((
+                        display_id.clone(),
+                        QualificationResult::error(QualificationError::UnknownDisplayId {
+                            display_id: display_id.clone(),
+                        }),
+                    ));
+                    continue;
+                }
+                [(experiment, variant)] => (*experiment, *variant),
+                _ => {
+                    opt_in_results.push((
+                        display_id.clone(),
+                        QualificationResult::error(QualificationError::AmbiguousDisplayId {
+                            display_id: display_id.clone(),
+                        }),
+                    ));
+                    continue;
+                }
+            };
+            if context.is_experiment_decided(experiment) {
+                opt_in_results.push((
+                    display_id.clone(),
+                    QualificationResult::new(
+                        QualificationResultType::NotQualified,
+                        format!("Experiment {} already decided", experiment.experiment_id),
+                    ),
+                ));
+                continue;
+            }
+            opt_in_results.push((
+                display_id.clone(),
+                QualificationResult::new(
+                    QualificationResultType::Qualified,
+                    format!("opt-in {}", display_id),
+                ),
+            ));
+            match opt_ins_by_experiment
+                .iter_mut()
+                .find(|(experiment_id, _)| *experiment_id == experiment.experiment_id)
+            {
+                Some((_, opt_ins)) => opt_ins.push((display_id.clone(), variant.variant_id)),
+                None => opt_ins_by_experiment.push((
+                    experiment.experiment_id,
+                    vec![(display_id.clone(), variant.variant_id)],
+                )),
+            }
+        }
+
+        for (experiment_id, opt_ins) in opt_ins_by_experiment {
+            if let [(display_id, variant_id)] = opt_ins.as_slice() {
+                context.record_mapper_result(
+                    "OptInMapper",
+                    *variant_id,
+                    QualificationResult::new(
+                        QualificationResultType::Qualified,
+                        format!("opt-in {}", display_id),
+                    ),
+                );
+                continue;
+            }
+            let conflict = QualificationResult::error(QualificationError::ConflictingDisplayIds {
+                experiment_id,
+                display_ids: opt_ins
+                    .iter()
+                    .map(|(display_id, _)| display_id.clone())
+                    .collect(),
+            });
+            log::error!("{}", conflict.qualification_result_reason);
+            for (display_id, variant_id) in opt_ins {
+                context.record_mapper_result("OptInMapper", variant_id, conflict.clone());
+                if let Some((_, opt_in_result)) =
+                    opt_in_results.iter_mut().find(|(id, _)| *id == display_id)
+                {
+                    *opt_in_result = conflict.clone();
+                }
+            }
+        }
+
+        context.opt_in_result_map.extend(opt_in_results);
+    }
 
     fn after(&self, context: &mut EvaluationContext) {
         log::debug!("#OptInMapper finished.");
     }
 }
 
+// Whitelisting: a user whose UID is listed in Variant::whitelisted_uids is Qualified for that variant,
+// bypassing traffic spectrums and targeting
 pub struct UidListMapper;
 
 impl Mapper for UidListMapper {
     fn before(&self, context: &mut EvaluationContext) {}
 
-    fn map(&self, context: &mut EvaluationContext) {}
+    fn map(&self, context: &mut EvaluationContext) {
+        let Some(uid) = context.context_map.get(UID_CONTEXT_KEY) else {
+            return;
+        };
+        let mut decisions = vec![];
+        for experiment in &context.experiment_list {
+            if context.is_experiment_decided(experiment) || context.is_experiment_mapped(experiment)
+            {
+                continue;
+            }
+            if let Some(variant) = experiment
+                .variants
+                .iter()
+                .find(|variant| variant.whitelisted_uids.contains(uid))
+            {
+                decisions.push(variant.variant_id);
+            }
+        }
+        for variant_id in decisions {
+            context.record_mapper_result(
+                "UidListMapper",
+                variant_id,
+                QualificationResult::new(QualificationResultType::Qualified, "whitelisted uid"),
+            );
+        }
+    }
 
     fn after(&self, context: &mut EvaluationContext) {
         log::debug!("#UidListMapper finished.");
     }
 }
 
+// Feature Flag rules: the first VariantRule (by rule_id) whose context_expression matches supplies
+// the variant spectrums through Target::variant_mod_map in place of each Variant::variant_mod,
+// ramped through Target::ramp_map. Experiments without a matching rule are left to the VariantMapper.
 pub struct VariantRuleMapper;
 
 impl Mapper for VariantRuleMapper {
     fn before(&self, context: &mut EvaluationContext) {}
 
-    fn map(&self, context: &mut EvaluationContext) {}
+    fn map(&self, context: &mut EvaluationContext) {
+        let mut decisions = vec![];
+        let mut matched_rule_ids = vec![];
+        for experiment in &context.experiment_list {
+            if experiment.variant_rules.is_empty()
+                || context.is_experiment_decided(experiment)
+                || context.is_experiment_mapped(experiment)
+            {
+                continue;
+            }
+            let Some(unit_value) = context.context_map.get(&experiment.randomization_unit_key)
+            else {
+                continue;
+            };
+            let mut variant_rules: Vec<_> = experiment.variant_rules.iter().collect();
+            variant_rules.sort_by_key(|variant_rule| variant_rule.rule_id);
+            for variant_rule in variant_rules {
+                if !variant_rule.context_expression.trim().is_empty() {
+                    match Expression::parse(&variant_rule.context_expression) {
+                        Ok(expression) if expression.matches(&context.context_map) => {}
+                        Ok(_) => continue,
+                        Err(parse_error) => {
+                            decisions.extend(error_results(
+                                experiment,
+                                QualificationError::MalformedExpression {
+                                    experiment_id: experiment.experiment_id,
+                                    rule_id: Some(variant_rule.rule_id),
+                                    parse_error,
+                                },
+                            ));
+                            break;
+                        }
+                    }
+                }
+                let variant_mod_of = |variant: &Variant| {
+                    variant_rule.target.variant_mod_map.get(&variant.variant_id)
+                };
+                if let Some(qualification_error) =
+                    invalid_spectrum(experiment, Some(variant_rule.rule_id), variant_mod_of)
+                {
+                    decisions.extend(error_results(experiment, qualification_error));
+                    break;
+                }
+                let ramped_variant_mods =
+                    ramped_variant_mods(experiment, context.now_millis, |variant| {
+                        Some((
+                            variant_mod_of(variant)?,
+                            variant_rule.target.ramp_map.get(&variant.variant_id)?,
+                        ))
+                    });
+                let assignment = assign_variant_with(experiment, unit_value, |variant| {
+                    ramped_variant_mods
+                        .get(&variant.variant_id)
+                        .or_else(|| variant_mod_of(variant))
+                });
+                matched_rule_ids.push((experiment.experiment_id, variant_rule.rule_id));
+                decisions.extend(bucket_assignment_results(
+                    experiment,
+                    assignment,
+                    &format!("rule_id {}: ", variant_rule.rule_id),
+                ));
+                break;
+            }
+        }
+        for (variant_id, qualification_result) in decisions {
+            context.record_mapper_result("VariantRuleMapper", variant_id, qualification_result);
+        }
+        context.matched_rule_id_map.extend(matched_rule_ids);
+    }
 
     fn after(&self, context: &mut EvaluationContext) {
         log::debug!("#VariantRuleMapper finished.");
     }
 }
 
+// Spectrum based assignment for every experiment no earlier Mapper has taken care of, with
+// Variant::ramp applied: the bucketed variant is Deferred, or every variant is NotQualified when
+// the bucket is not allocated
 pub struct VariantMapper;
 
 impl Mapper for VariantMapper {
     fn before(&self, context: &mut EvaluationContext) {}
 
-    fn map(&self, context: &mut EvaluationContext) {}
+    fn map(&self, context: &mut EvaluationContext) {
+        let mut decisions = vec![];
+        for experiment in &context.experiment_list {
+            if context.is_experiment_decided(experiment) || context.is_experiment_mapped(experiment)
+            {
+                continue;
+            }
+            let Some(unit_value) = context.context_map.get(&experiment.randomization_unit_key)
+            else {
+                continue;
+            };
+            if let Some(qualification_error) =
+                invalid_spectrum(experiment, None, |variant| Some(&variant.variant_mod))
+            {
+                decisions.extend(error_results(experiment, qualification_error));
+                continue;
+            }
+            let ramped_variant_mods =
+                ramped_variant_mods(experiment, context.now_millis, |variant| {
+                    Some((&variant.variant_mod, variant.ramp.as_ref()?))
+                });
+            let assignment = assign_variant_with(experiment, unit_value, |variant| {
+                ramped_variant_mods
+                    .get(&variant.variant_id)
+                    .or(Some(&variant.variant_mod))
+            });
+            decisions.extend(bucket_assignment_results(experiment, assignment, ""));
+        }
+        for (variant_id, qualification_result) in decisions {
+            context.record_mapper_result("VariantMapper", variant_id, qualification_result);
+        }
+    }
 
     fn after(&self, context: &mut EvaluationContext) {
         log::debug!("#VariantMapper finished.");
     }
 }
 
+// Spectrums of the ramped variants at now_millis, by variant_id
+fn ramped_variant_mods<'a, F>(
+    experiment: &'a Experiment,
+    now_millis: u64,
+    ramp_of: F,
+) -> HashMap<i32, Traffic>
+where
+    F: Fn(&'a Variant) -> Option<(&'a Traffic, &'a RampSchedule)>,
+{
+    experiment
+        .variants
+        .iter()
+        .filter_map(|variant| {
+            let (traffic, ramp_schedule) = ramp_of(variant)?;
+            Some((
+                variant.variant_id,
+                ramp_schedule.ramp(traffic, experiment.bucket_count, now_millis),
+            ))
+        })
+        .collect()
+}
+
+// Mapper results of a spectrum based assignment: the bucketed variant is Deferred,
+// or every variant is NotQualified when the bucket is not allocated
+fn bucket_assignment_results(
+    experiment: &Experiment,
+    assignment: BucketAssignment,
+    reason_prefix: &str,
+) -> Vec<(i32, QualificationResult)> {
+    let reason = match assignment {
+        BucketAssignment::Assigned { bucket, variant } => {
+            return vec![(
+                variant.variant_id,
+                QualificationResult::new(
+                    QualificationResultType::Deferred,
+                    format!("{}Bucket {} in variant_mod", reason_prefix, bucket),
+                ),
+            )];
+        }
+        BucketAssignment::OutsideBaseMod { bucket } => {
+            format!("{}Bucket {} outside base_mod", reason_prefix, bucket)
+        }
+        BucketAssignment::Unallocated { bucket } => {
+            format!(
+                "{}Bucket {} not allocated to any variant",
+                reason_prefix, bucket
+            )
+        }
+    };
+    experiment
+        .variants
+        .iter()
+        .map(|variant| {
+            (
+                variant.variant_id,
+                QualificationResult::new(QualificationResultType::NotQualified, reason.clone()),
+            )
+        })
+        .collect()
+}
+
+// Error results for every variant of an experiment
+fn error_results(
+    experiment: &Experiment,
+    qualification_error: QualificationError,
+) -> Vec<(i32, QualificationResult)> {
+    log::error!(
+        "Experiment {}: {}",
+        experiment.experiment_id,
+        qualification_error
+    );
+    experiment
+        .variants
+        .iter()
+        .map(|variant| {
+            (
+                variant.variant_id,
+                QualificationResult::error(qualification_error.clone()),
+            )
+        })
+        .collect()
+}
+
+// First unusable spectrum among base_mod and the variant spectrums supplied by variant_mod_of
+fn invalid_spectrum<'a>(
+    experiment: &'a Experiment,
+    rule_id: Option<i32>,
+    variant_mod_of: impl Fn(&'a Variant) -> Option<&'a Traffic>,
+) -> Option<QualificationError> {
+    let base_mod = (None, Some(&experiment.base_mod));
+    let variant_mods = experiment
+        .variants
+        .iter()
+        .map(|variant| (Some(variant.variant_id), variant_mod_of(variant)));
+    std::iter::once(base_mod)
+        .chain(variant_mods)
+        .find_map(|(variant_id, traffic)| {
+            let message = traffic?.validate(experiment.bucket_count).err()?;
+            Some(QualificationError::InvalidSpectrum {
+                experiment_id: experiment.experiment_id,
+                variant_id,
+                rule_id: variant_id.and(rule_id),
+                message,
+            })
+        })
+}
+
+// Mutually exclusive layers: among the assigned experiments of a layer only one qualifies,
+// a forced (opt-in, whitelist) assignment first, then by priority and lowest experiment_id.
+// Every variant of the other experiments in the layer is NotQualified.
 pub struct CollisionResolvePhase;
 
 impl Phase for CollisionResolvePhase {
     fn before(&self, context: &mut EvaluationContext) {}
 
-    fn execute(&self, context: &mut EvaluationContext) {}
+    fn execute(&self, context: &mut EvaluationContext) {
+        // layer -> (forced, experiment) of every assigned experiment
+        let mut candidates_by_layer: HashMap<&str, Vec<(bool, &Experiment)>> = HashMap::new();
+        for experiment in &context.experiment_list {
+            let Some(layer) = &experiment.layer else {
+                continue;
+            };
+            if context.assigned_variant_ids(experiment).is_empty() {
+                continue;
+            }
+            candidates_by_layer
+                .entry(layer)
+                .or_default()
+                .push((context.is_experiment_forced(experiment), experiment));
+        }
+        let mut decisions = vec![];
+        for (layer, mut candidates) in candidates_by_layer {
+            if candidates.len() < 2 {
+                continue;
+            }
+            candidates.sort_by_key(|(forced, experiment)| {
+                (!forced, experiment.priority, experiment.experiment_id)
+            });
+            let winner_experiment_id = candidates[0].1.experiment_id;
+            for (_, experiment) in &candidates[1..] {
+                let reason = format!(
+                    "Collision in layer {}: experiment {} qualified",
+                    layer, winner_experiment_id
+                );
+                for variant in &experiment.variants {
+                    decisions.push((
+                        variant.variant_id,
+                        QualificationResult::new(
+                            QualificationResultType::NotQualified,
+                            reason.clone(),
+                        ),
+                    ));
+                }
+            }
+        }
+        for (variant_id, qualification_result) in decisions {
+            context.record_phase_result("CollisionResolvePhase", variant_id, qualification_result);
+        }
+    }
 
     fn after(&self, context: &mut EvaluationContext) {
         log::debug!("#CollisionResolvePhase finished.");
     }
 }
 
-pub struct PrioritizationPhase;
+// Caps the number of assigned experiments per request, keeping those with the lowest Experiment::priority.
+// Forced (opt-in, whitelist) experiments are never demoted but take up their slots first;
+// the remaining assigned experiments beyond the cap are NotQualified.
+// More forced experiments than the cap all stay Qualified, so such a request exceeds the cap.
+#[derive(Default)]
+pub struct PrioritizationPhase {
+    pub max_qualified_experiments: Option<usize>,
+}
 
 impl Phase for PrioritizationPhase {
     fn before(&self, context: &mut EvaluationContext) {}
 
-    fn execute(&self, context: &mut EvaluationContext) {}
+    fn execute(&self, context: &mut EvaluationContext) {
+        let Some(max_qualified_experiments) = self.max_qualified_experiments else {
+            return;
+        };
+        let mut assigned: Vec<_> = context
+            .experiment_list
+            .iter()
+            .filter(|experiment| !context.assigned_variant_ids(experiment).is_empty())
+            .collect();
+        assigned.sort_by_key(|experiment| experiment.priority);
+        let forced_count = assigned
+            .iter()
+            .filter(|experiment| context.is_experiment_forced(experiment))
+            .count();
+        let mut remaining_slots = max_qualified_experiments.saturating_sub(forced_count);
+        let mut decisions = vec![];
+        for experiment in assigned {
+            if context.is_experiment_forced(experiment) {
+                continue;
+            }
+            if remaining_slots > 0 {
+                remaining_slots -= 1;
+                continue;
+            }
+            for variant in &experiment.variants {
+                decisions.push(variant.variant_id);
+            }
+        }
+        for variant_id in decisions {
+            context.record_phase_result(
+                "PrioritizationPhase",
+                variant_id,
+                QualificationResult::new(
+                    QualificationResultType::NotQualified,
+                    format!(
+                        "Priority cap of {} qualified experiments exceeded",
+                        max_qualified_experiments
+                    ),
+                ),
+            );
+        }
+    }
 
     fn after(&self, context: &mut EvaluationContext) {
         log::debug!("#PrioritizationPhase finished.");
     }
 }
 
+// Experiment level targeting: every variant of an experiment whose context_expression
+// does not match the context_map is NotQualified. An empty expression targets everyone,
+// and experiments forced by a Mapper are not targeted.
 pub struct ContextPhase;
 
 impl Phase for ContextPhase {
     fn before(&self, context: &mut EvaluationContext) {}
 
-    fn execute(&self, context: &mut EvaluationContext) {}
+    fn execute(&self, context: &mut EvaluationContext) {
+        let mut decisions = vec![];
+        for experiment in &context.experiment_list {
+            if experiment.context_expression.trim().is_empty()
+                || context.is_experiment_decided(experiment)
+                || context.is_experiment_forced(experiment)
+            {
+                continue;
+            }
+            let qualification_result = match Expression::parse(&experiment.context_expression) {
+                Ok(expression) => match expression.evaluate(&context.context_map) {
+                    Ok(()) => continue,
+                    Err(mismatch) => QualificationResult::new(
+                        QualificationResultType::NotQualified,
+                        format!("Context not matched: {}", mismatch),
+                    ),
+                },
+                Err(parse_error) => {
+                    let qualification_error = QualificationError::MalformedExpression {
+                        experiment_id: experiment.experiment_id,
+                        rule_id: None,
+                        parse_error,
+                    };
+                    log::error!(
+                        "Experiment {}: {}",
+                        experiment.experiment_id,
+                        qualification_error
+                    );
+                    QualificationResult::error(qualification_error)
+                }
+            };
+            for variant in &experiment.variants {
+                decisions.push((variant.variant_id, qualification_result.clone()));
+            }
+        }
+        for (variant_id, qualification_result) in decisions {
+            context.record_phase_result("ContextPhase", variant_id, qualification_result);
+        }
+    }
 
     fn after(&self, context: &mut EvaluationContext) {
         log::debug!("#ContextPhase finished.");
     }
 }
 
+// Final result of every variant in experiment_list, by precedence:
+// a Phase decision, then the Mapper assignment (Deferred is promoted to Qualified), then NotQualified.
+// A request aborted under ErrorMode::AbortRequest never gets here and its variants carry no result.
 pub struct ResultPackagedPhase;
 
 impl Phase for ResultPackagedPhase {
     fn before(&self, context: &mut EvaluationContext) {}
 
-    fn execute(&self, context: &mut EvaluationContext) {}
+    fn execute(&self, context: &mut EvaluationContext) {
+        let mut decisions = vec![];
+        for experiment in &context.experiment_list {
+            let mut packaged = vec![];
+            for variant in &experiment.variants {
+                let mapper_result =
+                    context
+                        .result_by_mapper
+                        .values()
+                        .find_map(|evaluation_result| {
+                            evaluation_result
+                                .variant_result_map
+                                .get(&variant.variant_id)
+                        });
+                let qualification_result = match (
+                    context.result.variant_result_map.get(&variant.variant_id),
+                    mapper_result,
+                ) {
+                    (Some(final_result), _) => final_result.clone(),
+                    (None, Some(mapper_result)) => match mapper_result.qualification_result_type {
+                        QualificationResultType::Deferred => QualificationResult::new(
+                            QualificationResultType::Qualified,
+                            mapper_result.qualification_result_reason.clone(),
+                        ),
+                        _ => mapper_result.clone(),
+                    },
+                    (None, None) => continue,
+                };
+                packaged.push((variant.variant_id, qualification_result));
+            }
+            let qualified_variant = packaged.iter().find(|(_, qualification_result)| {
+                qualification_result.qualification_result_type == QualificationResultType::Qualified
+            });
+            let unassigned_reason = match qualified_variant {
+                Some((variant_id, _)) => format!("Variant {} assigned", variant_id),
+                None => "Not assigned".to_string(),
+            };
+            for variant in &experiment.variants {
+                if !packaged
+                    .iter()
+                    .any(|(variant_id, _)| *variant_id == variant.variant_id)
+                {
+                    packaged.push((
+                        variant.variant_id,
+                        QualificationResult::new(
+                            QualificationResultType::NotQualified,
+                            unassigned_reason.clone(),
+                        ),
+                    ));
+                }
+            }
+            decisions.extend(packaged);
+        }
+        context.result.variant_result_map.extend(decisions);
+    }
 
     fn after(&self, context: &mut EvaluationContext) {
         log::debug!("#ResultPackagePhase finished.");
@@ -193,15 +866,53 @@ impl Phase for ResultPackagedPhase {
 
 pub struct QualificationEngine {
     pub phases: Vec<Box<dyn Phase>>,
+    pub clock: Arc<dyn Clock>,
 }
 
 impl QualificationEngine {
-    fn qualify(&self, context: &mut EvaluationContext) {
+    // Default engine whose PrioritizationPhase qualifies at most the given number of experiments per request
+    pub fn with_max_qualified_experiments(max_qualified_experiments: usize) -> Self {
+        QualificationEngine {
+            phases: default_phases(PrioritizationPhase {
+                max_qualified_experiments: Some(max_qualified_experiments),
+            }),
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    // Evaluate schedules and timestamp exposures against the given clock instead of the wall clock
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    // Hold users out of experiments through a HoldoutPhase run right before the MappingPhase,
+    // failing on the first invalid Holdout
+    pub fn with_holdouts(mut self, holdouts: Vec<Holdout>) -> Result<Self, String> {
+        for holdout in &holdouts {
+            holdout
+                .validate()
+                .map_err(|message| format!("Holdout {}: {}", holdout.holdout_id, message))?;
+        }
+        let index = self
+            .phases
+            .iter()
+            .position(|phase| phase.name() == "MappingPhase")
+            .unwrap_or(self.phases.len());
+        self.phases
+            .insert(index, Box::new(HoldoutPhase { holdouts }));
+        Ok(self)
+    }
+
+    pub(crate) fn qualify(&self, context: &mut EvaluationContext) {
+        context.now_millis = self.clock.now_millis();
         for individual_phase in self.phases.iter() {
-            individual_phase.before(context);
-            individual_phase.execute(context);
-            individual_phase.after(context);
-            if context.error_code > 0 {
+            context.traced(individual_phase.name(), TraceStepKind::Phase, |context| {
+                individual_phase.before(context);
+                individual_phase.execute(context);
+                individual_phase.after(context);
+            });
+            if context.error.is_some() {
                 break;
             }
         }
@@ -211,30 +922,37 @@ impl QualificationEngine {
 impl Default for QualificationEngine {
     fn default() -> Self {
         QualificationEngine {
-            phases: vec![
-                Box::new(InitializationPhase),
-                Box::new(ValidationPhase),
-                Box::new(MappingPhase {
-                    mappers: vec![
-                        Box::new(OptInMapper),
-                        Box::new(UidListMapper),
-                        Box::new(VariantRuleMapper),
-                    ],
-                }),
-                Box::new(CollisionResolvePhase),
-                Box::new(PrioritizationPhase),
-                Box::new(ContextPhase),
-                Box::new(ResultPackagedPhase),
-            ],
+            phases: default_phases(PrioritizationPhase::default()),
+            clock: Arc::new(SystemClock),
         }
     }
 }
 
+fn default_phases(prioritization_phase: PrioritizationPhase) -> Vec<Box<dyn Phase>> {
+    vec![
+        Box::new(InitializationPhase),
+        Box::new(SchedulePhase),
+        Box::new(ValidationPhase),
+        Box::new(MappingPhase {
+            mappers: vec![
+                Box::new(OptInMapper),
+                Box::new(UidListMapper),
+                Box::new(VariantRuleMapper),
+                Box::new(VariantMapper),
+            ],
+        }),
+        Box::new(ContextPhase),
+        Box::new(CollisionResolvePhase),
+        Box::new(prioritization_phase),
+        Box::new(ResultPackagedPhase),
+    ]
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::core_qualification_dto::EvaluationResult;
-    use crate::ep_dto::{Experiment, Target, Traffic, Variant, VariantRule};
+    use crate::ep_dto::{Experiment, RampStep, Target, Traffic, Variant, VariantRule};
     use mockall::predicate::*;
     use mockall::*;
     use std::any::Any;
@@ -249,7 +967,7 @@ mod tests {
     #[test]
     fn qualification_engine_creation() {
         let engine = QualificationEngine::default();
-        assert_eq!(engine.phases.len(), 7);
+        assert_eq!(engine.phases.len(), 8);
     }
 
     #[test]
@@ -260,8 +978,9 @@ mod tests {
             variant_id: 1024,
             variant_display_id: "0aX0".to_string(),
             variant_flags: 0,
-            variant_mod: Traffic { spectrum: "1111111111111111111111111111111111111111111111111100000000000000000000000000000000000000000000000000".to_string() },
-            whitelisted_uids: vec!["1038812".to_string()]
+            variant_mod: Traffic::try_from("1111111111111111111111111111111111111111111111111100000000000000000000000000000000000000000000000000".to_string()).unwrap(),
+            whitelisted_uids: vec!["1038812".to_string()],
+            ramp: None,
         };
         let color_blue_variant = Variant {
             name: "Blue Variant".to_string(),
@@ -269,17 +988,19 @@ mod tests {
             variant_id: 1025,
             variant_display_id: "0aX1".to_string(),
             variant_flags: 0,
-            variant_mod: Traffic { spectrum: "0000000000000000000000000000000000000000000000000011111111111111111111111111111111111111111111111111".to_string() },
-            whitelisted_uids: vec!["1015529".to_string()]
+            variant_mod: Traffic::try_from("0000000000000000000000000000000000000000000000000011111111111111111111111111111111111111111111111111".to_string()).unwrap(),
+            whitelisted_uids: vec!["1015529".to_string()],
+            ramp: None,
         };
         let mut first_variant_mod_map = HashMap::new();
-        first_variant_mod_map.insert(1024, Traffic { spectrum: "0000000000000000000000000000000000000000000000000011111111111111111111111111111111111111111111111111".to_string() });
-        first_variant_mod_map.insert(1025, Traffic { spectrum: "1111111111111111111111111111111111111111111111111100000000000000000000000000000000000000000000000000".to_string() });
+        first_variant_mod_map.insert(1024, Traffic::try_from("0000000000000000000000000000000000000000000000000011111111111111111111111111111111111111111111111111".to_string()).unwrap());
+        first_variant_mod_map.insert(1025, Traffic::try_from("1111111111111111111111111111111111111111111111111100000000000000000000000000000000000000000000000000".to_string()).unwrap());
         let first_variant_rule = VariantRule {
             rule_id: 0,
             context_expression: "AND(IN(SITEID, 0), IN(CHANNELID, 1))".to_string(),
             target: Target {
                 variant_mod_map: first_variant_mod_map,
+                ramp_map: HashMap::new(),
             },
         };
         let color_experiment = Experiment {
@@ -290,8 +1011,14 @@ mod tests {
             experiment_flags: 0,
             variant_rules: vec![first_variant_rule],
             variants: vec![color_red_variant, color_blue_variant],
-            base_mod: Traffic { spectrum: "1111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111".to_string() },
+            base_mod: Traffic::try_from("1111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111".to_string()).unwrap(),
             randomization_unit_key: "LOOKUP_ID".to_string(),
+            layer: None,
+            priority: 0,
+            bucket_count: 100,
+            start_time: None,
+            end_time: None,
+            paused: false,
         };
         let mut context_map: HashMap<String, String> = HashMap::new();
         context_map.insert("LOOKUP_ID".to_string(), "search_88ax9i5".to_string());
@@ -309,10 +1036,33 @@ mod tests {
 
         engine.qualify(&mut evaluation_context);
         assert_eq!(evaluation_context.experiment_list.len(), 1);
-        assert_eq!(evaluation_context.result.variant_result_map.len(), 0);
+        assert_eq!(
+            evaluation_context.result.variant_result_map,
+            HashMap::from([
+                (
+                    1024,
+                    QualificationResult::new(QualificationResultType::Qualified, "opt-in 0aX0")
+                ),
+                (
+                    1025,
+                    QualificationResult::new(
+                        QualificationResultType::NotQualified,
+                        "Variant 1024 assigned"
+                    )
+                ),
+            ])
+        );
+        assert_eq!(
+            evaluation_context.result_by_mapper["OptInMapper"].variant_result_map[&1024],
+            QualificationResult::new(QualificationResultType::Qualified, "opt-in 0aX0")
+        );
+        assert!(
+            !evaluation_context
+                .result_by_mapper
+                .contains_key("UidListMapper")
+        );
         assert_eq!(evaluation_context.result_by_phase.len(), 0);
-        assert_eq!(evaluation_context.error_code, 0);
-        assert_eq!(evaluation_context.error_message, "");
+        assert_eq!(evaluation_context.error, None);
     }
 
     #[test]
@@ -323,8 +1073,9 @@ mod tests {
             variant_id: 1024,
             variant_display_id: "0aX0".to_string(),
             variant_flags: 0,
-            variant_mod: Traffic { spectrum: "1111111111111111111111111111111111111111111111111100000000000000000000000000000000000000000000000000".to_string() },
-            whitelisted_uids: vec!["1038812".to_string()]
+            variant_mod: Traffic::try_from("1111111111111111111111111111111111111111111111111100000000000000000000000000000000000000000000000000".to_string()).unwrap(),
+            whitelisted_uids: vec!["1038812".to_string()],
+            ramp: None,
         };
         let color_blue_variant = Variant {
             name: "Blue Variant".to_string(),
@@ -332,17 +1083,19 @@ mod tests {
             variant_id: 1025,
             variant_display_id: "0aX1".to_string(),
             variant_flags: 0,
-            variant_mod: Traffic { spectrum: "0000000000000000000000000000000000000000000000000011111111111111111111111111111111111111111111111111".to_string() },
-            whitelisted_uids: vec!["1015529".to_string()]
+            variant_mod: Traffic::try_from("0000000000000000000000000000000000000000000000000011111111111111111111111111111111111111111111111111".to_string()).unwrap(),
+            whitelisted_uids: vec!["1015529".to_string()],
+            ramp: None,
         };
         let mut first_variant_mod_map = HashMap::new();
-        first_variant_mod_map.insert(1024, Traffic { spectrum: "0000000000000000000000000000000000000000000000000011111111111111111111111111111111111111111111111111".to_string() });
-        first_variant_mod_map.insert(1025, Traffic { spectrum: "1111111111111111111111111111111111111111111111111100000000000000000000000000000000000000000000000000".to_string() });
+        first_variant_mod_map.insert(1024, Traffic::try_from("0000000000000000000000000000000000000000000000000011111111111111111111111111111111111111111111111111".to_string()).unwrap());
+        first_variant_mod_map.insert(1025, Traffic::try_from("1111111111111111111111111111111111111111111111111100000000000000000000000000000000000000000000000000".to_string()).unwrap());
         let first_variant_rule = VariantRule {
             rule_id: 0,
             context_expression: "AND(IN(SITEID, 0), IN(CHANNELID, 1))".to_string(),
             target: Target {
                 variant_mod_map: first_variant_mod_map,
+                ramp_map: HashMap::new(),
             },
         };
         let color_experiment = Experiment {
@@ -353,8 +1106,14 @@ mod tests {
             experiment_flags: 0,
             variant_rules: vec![first_variant_rule],
             variants: vec![color_red_variant, color_blue_variant],
-            base_mod: Traffic { spectrum: "1111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111".to_string() },
+            base_mod: Traffic::try_from("1111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111".to_string()).unwrap(),
             randomization_unit_key: "LOOKUP_ID".to_string(),
+            layer: None,
+            priority: 0,
+            bucket_count: 100,
+            start_time: None,
+            end_time: None,
+            paused: false,
         };
         let mut context_map: HashMap<String, String> = HashMap::new();
         context_map.insert("UID".to_string(), "1015529".to_string());
@@ -373,10 +1132,968 @@ mod tests {
         assert_eq!(evaluation_context.experiment_list.len(), 1);
         assert_eq!(evaluation_context.result.variant_result_map.len(), 0);
         assert_eq!(evaluation_context.result_by_phase.len(), 0);
-        assert_eq!(evaluation_context.error_code, 1);
+        let error = evaluation_context.error.unwrap();
+        assert_eq!(
+            error,
+            QualificationError::MissingContextKey {
+                experiment_id: 65536,
+                key: "LOOKUP_ID".to_string()
+            }
+        );
+        assert_eq!(error.code(), 1);
+        assert_eq!(error.to_string(), "Missing context key LOOKUP_ID");
+    }
+
+    fn color_experiment() -> Experiment {
+        let color_red_variant = Variant {
+            name: "Red Variant".to_string(),
+            value: "#FF0000".to_string(),
+            variant_id: 1024,
+            variant_display_id: "0aX0".to_string(),
+            variant_flags: 0,
+            variant_mod: Traffic::try_from("1111111111111111111111111111111111111111111111111100000000000000000000000000000000000000000000000000".to_string()).unwrap(),
+            whitelisted_uids: vec!["1038812".to_string()],
+            ramp: None,
+        };
+        let color_blue_variant = Variant {
+            name: "Blue Variant".to_string(),
+            value: "#00FF00".to_string(),
+            variant_id: 1025,
+            variant_display_id: "0aX1".to_string(),
+            variant_flags: 0,
+            variant_mod: Traffic::try_from("0000000000000000000000000000000000000000000000000011111111111111111111111111111111111111111111111111".to_string()).unwrap(),
+            whitelisted_uids: vec!["1015529".to_string()],
+            ramp: None,
+        };
+        let mut first_variant_mod_map = HashMap::new();
+        first_variant_mod_map.insert(1024, Traffic::try_from("0000000000000000000000000000000000000000000000000011111111111111111111111111111111111111111111111111".to_string()).unwrap());
+        first_variant_mod_map.insert(1025, Traffic::try_from("1111111111111111111111111111111111111111111111111100000000000000000000000000000000000000000000000000".to_string()).unwrap());
+        let first_variant_rule = VariantRule {
+            rule_id: 0,
+            context_expression: "AND(IN(SITEID, 0), IN(CHANNELID, 1))".to_string(),
+            target: Target {
+                variant_mod_map: first_variant_mod_map,
+                ramp_map: HashMap::new(),
+            },
+        };
+        Experiment {
+            name: "Color Experiment".to_string(),
+            context_expression: "AND(IN(SITEID, 0, 77), IN(CHANNELID, 1, 5, 6), EQ(F90D, \"TRUE\"))".to_string(),
+            hashing_constant: "0XF23AC".to_string(),
+            experiment_id: 65536,
+            experiment_flags: 0,
+            variant_rules: vec![first_variant_rule],
+            variants: vec![color_red_variant, color_blue_variant],
+            base_mod: Traffic::try_from("1111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111".to_string()).unwrap(),
+            randomization_unit_key: "LOOKUP_ID".to_string(),
+            layer: None,
+            priority: 0,
+            bucket_count: 100,
+            start_time: None,
+            end_time: None,
+            paused: false,
+        }
+    }
+
+    fn color_context_map(site_id: &str) -> HashMap<String, String> {
+        let mut context_map: HashMap<String, String> = HashMap::new();
+        context_map.insert("LOOKUP_ID".to_string(), "search_88ax9i5".to_string());
+        context_map.insert("UID".to_string(), "1015529".to_string());
+        context_map.insert("SITEID".to_string(), site_id.to_string());
+        context_map.insert("CHANNELID".to_string(), "6".to_string());
+        context_map.insert("F90D".to_string(), "TRUE".to_string());
+        context_map
+    }
+
+    #[test]
+    fn context_phase_keeps_matching_experiment() {
+        let mut evaluation_context = EvaluationContext {
+            experiment_list: vec![color_experiment()],
+            context_map: color_context_map("77"),
+            ..Default::default()
+        };
+        ContextPhase.execute(&mut evaluation_context);
+        assert_eq!(evaluation_context.result.variant_result_map.len(), 0);
+        assert_eq!(evaluation_context.result_by_phase.len(), 0);
+    }
+
+    #[test]
+    fn context_phase_excludes_non_matching_experiment() {
+        let mut evaluation_context = EvaluationContext {
+            experiment_list: vec![color_experiment()],
+            context_map: color_context_map("100"),
+            ..Default::default()
+        };
+        ContextPhase.execute(&mut evaluation_context);
+        let expected = QualificationResult::new(
+            QualificationResultType::NotQualified,
+            "Context not matched: IN(SITEID, 0, 77) failed with SITEID=100",
+        );
         assert_eq!(
-            evaluation_context.error_message,
-            "Missing context key LOOKUP_ID"
+            evaluation_context.result.variant_result_map[&1024],
+            expected
         );
+        assert_eq!(
+            evaluation_context.result.variant_result_map[&1025],
+            expected
+        );
+        assert_eq!(
+            evaluation_context.result_by_phase["ContextPhase"]
+                .variant_result_map
+                .len(),
+            2
+        );
+    }
+
+    #[test]
+    fn context_phase_reports_malformed_expression() {
+        let mut color_experiment = color_experiment();
+        color_experiment.context_expression = "AND(IN(SITEID, 0, 77)".to_string();
+        let mut untargeted_experiment = self::color_experiment();
+        untargeted_experiment.experiment_id = 65537;
+        untargeted_experiment.context_expression = "".to_string();
+        for variant in untargeted_experiment.variants.iter_mut() {
+            variant.variant_id += 2;
+        }
+        let mut evaluation_context = EvaluationContext {
+            experiment_list: vec![color_experiment, untargeted_experiment],
+            context_map: color_context_map("100"),
+            ..Default::default()
+        };
+        ContextPhase.execute(&mut evaluation_context);
+        assert_eq!(evaluation_context.result.variant_result_map.len(), 2);
+        let malformed = &evaluation_context.result.variant_result_map[&1024];
+        assert_eq!(
+            malformed.qualification_result_reason,
+            "Malformed context expression: expected ',' or ')', found end of input at position 21"
+        );
+        assert_eq!(
+            malformed
+                .qualification_error
+                .as_ref()
+                .map(QualificationError::code),
+            Some(2)
+        );
+    }
+
+    #[test]
+    fn variant_mapper_defers_bucketed_variant() {
+        let mut evaluation_context = EvaluationContext {
+            experiment_list: vec![color_experiment()],
+            context_map: color_context_map("77"),
+            ..Default::default()
+        };
+        VariantMapper.map(&mut evaluation_context);
+        let bucket = crate::hashing::bucket_of("0XF23AC", "search_88ax9i5", 100);
+        let expected_variant_id = if bucket < 50 { 1024 } else { 1025 };
+        let mapped = &evaluation_context.result_by_mapper["VariantMapper"].variant_result_map;
+        assert_eq!(mapped.len(), 1);
+        assert_eq!(
+            mapped[&expected_variant_id],
+            QualificationResult::new(
+                QualificationResultType::Deferred,
+                format!("Bucket {} in variant_mod", bucket)
+            )
+        );
+    }
+
+    #[test]
+    fn variant_mapper_skips_units_outside_base_mod() {
+        let mut color_experiment = color_experiment();
+        color_experiment.base_mod = Traffic::try_from("0".repeat(100)).unwrap();
+        let mut evaluation_context = EvaluationContext {
+            experiment_list: vec![color_experiment],
+            context_map: color_context_map("77"),
+            ..Default::default()
+        };
+        VariantMapper.map(&mut evaluation_context);
+        let bucket = crate::hashing::bucket_of("0XF23AC", "search_88ax9i5", 100);
+        let mapped = &evaluation_context.result_by_mapper["VariantMapper"].variant_result_map;
+        assert_eq!(mapped.len(), 2);
+        assert_eq!(
+            mapped[&1025],
+            QualificationResult::new(
+                QualificationResultType::NotQualified,
+                format!("Bucket {} outside base_mod", bucket)
+            )
+        );
+    }
+
+    #[test]
+    fn qualification_engine_maps_variants() {
+        let mut context_map = color_context_map("77");
+        context_map.remove(UID_CONTEXT_KEY);
+        let mut evaluation_context = EvaluationContext {
+            experiment_list: vec![color_experiment()],
+            context_map,
+            ..Default::default()
+        };
+        QualificationEngine::default().qualify(&mut evaluation_context);
+        assert_eq!(
+            evaluation_context.result_by_mapper["VariantMapper"]
+                .variant_result_map
+                .len(),
+            1
+        );
+        let bucket = crate::hashing::bucket_of("0XF23AC", "search_88ax9i5", 100);
+        let (qualified_variant_id, other_variant_id) = if bucket < 50 {
+            (1024, 1025)
+        } else {
+            (1025, 1024)
+        };
+        assert_eq!(
+            evaluation_context.result.variant_result_map,
+            HashMap::from([
+                (
+                    qualified_variant_id,
+                    QualificationResult::new(
+                        QualificationResultType::Qualified,
+                        format!("Bucket {} in variant_mod", bucket)
+                    )
+                ),
+                (
+                    other_variant_id,
+                    QualificationResult::new(
+                        QualificationResultType::NotQualified,
+                        format!("Variant {} assigned", qualified_variant_id)
+                    )
+                ),
+            ])
+        );
+    }
+
+    #[test]
+    fn uid_list_mapper_forces_whitelisted_variant() {
+        let mut context_map = color_context_map("100");
+        context_map.insert(UID_CONTEXT_KEY.to_string(), "1038812".to_string());
+        let mut evaluation_context = EvaluationContext {
+            experiment_list: vec![color_experiment()],
+            context_map,
+            ..Default::default()
+        };
+        QualificationEngine::default().qualify(&mut evaluation_context);
+        let mapped = &evaluation_context.result_by_mapper["UidListMapper"].variant_result_map;
+        assert_eq!(mapped.len(), 1);
+        assert_eq!(
+            mapped[&1024],
+            QualificationResult::new(QualificationResultType::Qualified, "whitelisted uid")
+        );
+        // Neither spectrum based mapping nor targeting applies to a whitelisted user
+        assert!(
+            !evaluation_context
+                .result_by_mapper
+                .contains_key("VariantMapper")
+        );
+        assert_eq!(
+            evaluation_context.result.variant_result_map[&1024].qualification_result_type,
+            QualificationResultType::Qualified
+        );
+    }
+
+    #[test]
+    fn uid_list_mapper_ignores_unlisted_uid() {
+        let mut context_map = color_context_map("77");
+        context_map.insert(UID_CONTEXT_KEY.to_string(), "42".to_string());
+        let mut evaluation_context = EvaluationContext {
+            experiment_list: vec![color_experiment()],
+            context_map,
+            ..Default::default()
+        };
+        UidListMapper.map(&mut evaluation_context);
+        assert_eq!(evaluation_context.result_by_mapper.len(), 0);
+    }
+
+    #[test]
+    fn opt_in_mapper_forces_opted_in_variant() {
+        let mut evaluation_context = EvaluationContext {
+            experiment_list: vec![color_experiment()],
+            context_map: color_context_map("100"),
+            opt_in_variant_display_ids: vec!["0aX1".to_string(), "0aX1".to_string()],
+            ..Default::default()
+        };
+        OptInMapper.map(&mut evaluation_context);
+        let expected = QualificationResult::new(QualificationResultType::Qualified, "opt-in 0aX1");
+        assert_eq!(
+            evaluation_context.result_by_mapper["OptInMapper"].variant_result_map,
+            HashMap::from([(1025, expected.clone())])
+        );
+        assert_eq!(
+            evaluation_context.opt_in_result_map,
+            HashMap::from([("0aX1".to_string(), expected)])
+        );
+    }
+
+    #[test]
+    fn opt_in_mapper_reports_unknown_and_conflicting_display_ids() {
+        let mut evaluation_context = EvaluationContext {
+            experiment_list: vec![color_experiment()],
+            context_map: color_context_map("77"),
+            opt_in_variant_display_ids: vec![
+                "0aX0".to_string(),
+                "zzzz".to_string(),
+                "0aX1".to_string(),
+            ],
+            ..Default::default()
+        };
+        OptInMapper.map(&mut evaluation_context);
+        let conflict = QualificationResult::error(QualificationError::ConflictingDisplayIds {
+            experiment_id: 65536,
+            display_ids: vec!["0aX0".to_string(), "0aX1".to_string()],
+        });
+        assert_eq!(
+            conflict.qualification_result_reason,
+            "Conflicting opt-in variant display ids 0aX0, 0aX1 for experiment 65536"
+        );
+        assert_eq!(
+            evaluation_context.result_by_mapper["OptInMapper"].variant_result_map,
+            HashMap::from([(1024, conflict.clone()), (1025, conflict.clone())])
+        );
+        assert_eq!(evaluation_context.opt_in_result_map["0aX0"], conflict);
+        assert_eq!(evaluation_context.opt_in_result_map["0aX1"], conflict);
+        assert_eq!(
+            evaluation_context.opt_in_result_map["zzzz"],
+            QualificationResult::error(QualificationError::UnknownDisplayId {
+                display_id: "zzzz".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn variant_rule_mapper_uses_first_matching_rule() {
+        let mut color_experiment = color_experiment();
+        let mut second_variant_mod_map = HashMap::new();
+        second_variant_mod_map.insert(1024, Traffic::try_from("1".repeat(100)).unwrap());
+        color_experiment.variant_rules.insert(
+            0,
+            VariantRule {
+                rule_id: 7,
+                context_expression: "IN(SITEID, 0)".to_string(),
+                target: Target {
+                    variant_mod_map: second_variant_mod_map,
+                    ramp_map: HashMap::new(),
+                },
+            },
+        );
+        let mut context_map = color_context_map("0");
+        context_map.insert("CHANNELID".to_string(), "1".to_string());
+        let mut evaluation_context = EvaluationContext {
+            experiment_list: vec![color_experiment],
+            context_map,
+            ..Default::default()
+        };
+        VariantRuleMapper.map(&mut evaluation_context);
+        // rule_id 0 is evaluated before rule_id 7 and swaps the variant spectrums
+        let bucket = crate::hashing::bucket_of("0XF23AC", "search_88ax9i5", 100);
+        let expected_variant_id = if bucket < 50 { 1025 } else { 1024 };
+        assert_eq!(
+            evaluation_context.result_by_mapper["VariantRuleMapper"].variant_result_map,
+            HashMap::from([(
+                expected_variant_id,
+                QualificationResult::new(
+                    QualificationResultType::Deferred,
+                    format!("rule_id 0: Bucket {} in variant_mod", bucket)
+                )
+            )])
+        );
+        assert_eq!(
+            evaluation_context.matched_rule_id_map,
+            HashMap::from([(65536, 0)])
+        );
+    }
+
+    #[test]
+    fn engine_emits_exposures_of_qualified_variants() {
+        let sink = std::sync::Arc::new(crate::InMemoryExposureSink::default());
+        let engine = QualificationEngine::default().with_exposure_sink(sink.clone());
+        assert_eq!(engine.phases.len(), 9);
+        let mut context_map = color_context_map("0");
+        context_map.insert("CHANNELID".to_string(), "1".to_string());
+        context_map.remove(UID_CONTEXT_KEY);
+        let mut evaluation_context = EvaluationContext {
+            experiment_list: vec![color_experiment()],
+            context_map,
+            ..Default::default()
+        };
+        engine.qualify(&mut evaluation_context);
+        let bucket = crate::hashing::bucket_of("0XF23AC", "search_88ax9i5", 100);
+        let events = sink.take_events();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].experiment_id, 65536);
+        assert_eq!(events[0].variant_id, if bucket < 50 { 1025 } else { 1024 });
+        assert_eq!(events[0].randomization_unit_value, "search_88ax9i5");
+        assert_eq!(events[0].rule_id, Some(0));
+        assert!(events[0].timestamp > 0);
+
+        // Requests aborted by an error emit nothing
+        let mut evaluation_context = EvaluationContext {
+            experiment_list: vec![color_experiment()],
+            ..Default::default()
+        };
+        engine.qualify(&mut evaluation_context);
+        assert!(evaluation_context.error.is_some());
+        assert!(sink.events().is_empty());
+    }
+
+    #[test]
+    fn schedule_phase_excludes_inactive_experiments() {
+        let clock = Arc::new(crate::ManualClock::new(1_000));
+        let engine = QualificationEngine::default().with_clock(clock.clone());
+        let mut context_map = color_context_map("0");
+        context_map.insert("CHANNELID".to_string(), "1".to_string());
+        let scheduled_experiment = |start_time, end_time, paused| {
+            let mut experiment = color_experiment();
+            experiment.start_time = start_time;
+            experiment.end_time = end_time;
+            experiment.paused = paused;
+            experiment
+        };
+        let schedule_reason = |experiment: Experiment| {
+            let mut evaluation_context = EvaluationContext {
+                experiment_list: vec![experiment],
+                context_map: context_map.clone(),
+                ..Default::default()
+            };
+            engine.qualify(&mut evaluation_context);
+            evaluation_context
+                .result_by_phase
+                .get("SchedulePhase")
+                .map(|evaluation_result| {
+                    assert_eq!(evaluation_result.variant_result_map.len(), 2);
+                    evaluation_result.variant_result_map[&1024]
+                        .qualification_result_reason
+                        .clone()
+                })
+        };
+
+        let windowed = scheduled_experiment(Some(1_000), Some(2_000), false);
+        assert_eq!(schedule_reason(windowed.clone()), None);
+        assert_eq!(
+            schedule_reason(scheduled_experiment(None, None, true)),
+            Some("Not active: paused".to_string())
+        );
+        clock.set(999);
+        assert_eq!(
+            schedule_reason(windowed.clone()),
+            Some("Not active: starts at 1000".to_string())
+        );
+        clock.advance(1_001);
+        assert_eq!(
+            schedule_reason(windowed),
+            Some("Not active: ended at 2000".to_string())
+        );
+
+        // An inactive experiment needs no randomization unit and cannot be forced
+        context_map.remove("LOOKUP_ID");
+        let mut evaluation_context = EvaluationContext {
+            experiment_list: vec![scheduled_experiment(None, Some(2_000), false)],
+            context_map,
+            ..Default::default()
+        };
+        engine.qualify(&mut evaluation_context);
+        assert_eq!(evaluation_context.error, None);
+        assert_eq!(evaluation_context.now_millis, 2_000);
+        assert!(evaluation_context.result_by_mapper.is_empty());
+        assert!(evaluation_context.result.variant_result_map.values().all(
+            |qualification_result| qualification_result.qualification_result_type
+                == QualificationResultType::NotQualified
+        ));
+    }
+
+    #[test]
+    fn holdout_phase_excludes_held_out_users() {
+        let global_holdout = Holdout {
+            name: "global 2026".to_string(),
+            holdout_id: 1,
+            layer: None,
+            randomization_unit_key: "GUID".to_string(),
+            hashing_constant: "".to_string(),
+            traffic: Traffic::from_percent(5.0, 100).unwrap(),
+            bucket_count: 100,
+        };
+        let held_out_guid = (0..)
+            .map(|index| format!("guid_{}", index))
+            .find(|guid| bucket_of("EXPTHOLDOUT1", guid, 100) < 5)
+            .unwrap();
+        let kept_guid = (0..)
+            .map(|index| format!("guid_{}", index))
+            .find(|guid| bucket_of("EXPTHOLDOUT1", guid, 100) >= 5)
+            .unwrap();
+        let homepage_holdout = Holdout {
+            name: "homepage".to_string(),
+            holdout_id: 2,
+            layer: Some("homepage".to_string()),
+            traffic: Traffic::from_percent(100.0, 100).unwrap(),
+            ..global_holdout.clone()
+        };
+        let qualify = |holdouts: Vec<Holdout>, guid: &str, opt_in: &str| {
+            let mut context_map = color_context_map("77");
+            context_map.insert("GUID".to_string(), guid.to_string());
+            let mut evaluation_context = EvaluationContext {
+                experiment_list: vec![color_experiment(), layered_experiment(2, 2000)],
+                context_map,
+                opt_in_variant_display_ids: vec![opt_in.to_string()],
+                ..Default::default()
+            };
+            let engine = QualificationEngine::default()
+                .with_holdouts(holdouts)
+                .unwrap();
+            assert_eq!(engine.phases.len(), 9);
+            assert_eq!(engine.phases[3].name(), "HoldoutPhase");
+            engine.qualify(&mut evaluation_context);
+            evaluation_context
+                .result_by_phase
+                .get("HoldoutPhase")
+                .map(|evaluation_result| {
+                    let mut held_out: Vec<_> = evaluation_result
+                        .variant_result_map
+                        .iter()
+                        .map(|(variant_id, qualification_result)| {
+                            assert_eq!(
+                                qualification_result.qualification_result_type,
+                                QualificationResultType::NotQualified
+                            );
+                            (
+                                *variant_id,
+                                qualification_result.qualification_result_reason.clone(),
+                            )
+                        })
+                        .collect();
+                    held_out.sort();
+                    held_out
+                })
+                .unwrap_or_default()
+        };
+
+        // Whitelisting and opt-ins do not escape a holdout
+        let global_reason = "holdout global 2026".to_string();
+        assert_eq!(
+            qualify(vec![global_holdout.clone()], &held_out_guid, "20"),
+            vec![
+                (1024, global_reason.clone()),
+                (1025, global_reason.clone()),
+                (2000, global_reason.clone()),
+                (2001, global_reason),
+            ]
+        );
+        assert_eq!(
+            qualify(vec![global_holdout.clone()], &kept_guid, "20"),
+            vec![]
+        );
+        let homepage_reason = "holdout homepage".to_string();
+        assert_eq!(
+            qualify(
+                vec![homepage_holdout, global_holdout.clone()],
+                &kept_guid,
+                "20"
+            ),
+            vec![(2000, homepage_reason.clone()), (2001, homepage_reason)]
+        );
+
+        let invalid_holdout = Holdout {
+            bucket_count: 1000,
+            ..global_holdout
+        };
+        assert_eq!(
+            QualificationEngine::default()
+                .with_holdouts(vec![invalid_holdout])
+                .err(),
+            Some("Holdout 1: spectrum must be 1000 characters, found 100".to_string())
+        );
+    }
+
+    #[test]
+    fn variant_rule_mapper_falls_back_to_variant_mapper() {
+        let mut context_map = color_context_map("77");
+        context_map.remove(UID_CONTEXT_KEY);
+        let mut evaluation_context = EvaluationContext {
+            experiment_list: vec![color_experiment()],
+            context_map,
+            ..Default::default()
+        };
+        QualificationEngine::default().qualify(&mut evaluation_context);
+        assert!(
+            !evaluation_context
+                .result_by_mapper
+                .contains_key("VariantRuleMapper")
+        );
+        assert!(
+            evaluation_context
+                .result_by_mapper
+                .contains_key("VariantMapper")
+        );
+    }
+
+    fn layered_experiment(experiment_id: i32, first_variant_id: i32) -> Experiment {
+        let mut experiment = color_experiment();
+        experiment.experiment_id = experiment_id;
+        experiment.layer = Some("homepage".to_string());
+        experiment.variant_rules = vec![];
+        for (index, variant) in experiment.variants.iter_mut().enumerate() {
+            variant.variant_id = first_variant_id + index as i32;
+            variant.variant_display_id = format!("{}{}", experiment_id, index);
+        }
+        experiment
+    }
+
+    #[test]
+    fn collision_resolve_phase_keeps_one_experiment_per_layer() {
+        let mut context_map = color_context_map("77");
+        context_map.remove(UID_CONTEXT_KEY);
+        let mut evaluation_context = EvaluationContext {
+            experiment_list: vec![
+                layered_experiment(3, 3000),
+                layered_experiment(1, 1000),
+                layered_experiment(2, 2000),
+            ],
+            context_map,
+            ..Default::default()
+        };
+        QualificationEngine::default().qualify(&mut evaluation_context);
+        let collided =
+            &evaluation_context.result_by_phase["CollisionResolvePhase"].variant_result_map;
+        assert_eq!(collided.len(), 4);
+        assert_eq!(
+            collided[&2000],
+            QualificationResult::new(
+                QualificationResultType::NotQualified,
+                "Collision in layer homepage: experiment 1 qualified"
+            )
+        );
+        assert!(!collided.contains_key(&1000) && !collided.contains_key(&1001));
+    }
+
+    #[test]
+    fn collision_resolve_phase_prefers_forced_experiment() {
+        let mut context_map = color_context_map("77");
+        context_map.remove(UID_CONTEXT_KEY);
+        let mut evaluation_context = EvaluationContext {
+            experiment_list: vec![layered_experiment(1, 1000), layered_experiment(2, 2000)],
+            context_map,
+            opt_in_variant_display_ids: vec!["21".to_string()],
+            ..Default::default()
+        };
+        QualificationEngine::default().qualify(&mut evaluation_context);
+        let collided =
+            &evaluation_context.result_by_phase["CollisionResolvePhase"].variant_result_map;
+        assert_eq!(
+            collided[&1000],
+            QualificationResult::new(
+                QualificationResultType::NotQualified,
+                "Collision in layer homepage: experiment 2 qualified"
+            )
+        );
+        assert!(!collided.contains_key(&2001));
+    }
+
+    #[test]
+    fn prioritization_phase_caps_experiments_by_priority() {
+        let mut context_map = color_context_map("77");
+        context_map.remove(UID_CONTEXT_KEY);
+        let mut experiment_list = vec![];
+        for (experiment_id, priority) in [(1, 5), (2, 0), (3, 9), (4, 1)] {
+            let mut experiment = layered_experiment(experiment_id, experiment_id * 1000);
+            experiment.layer = None;
+            experiment.priority = priority;
+            experiment_list.push(experiment);
+        }
+        let mut evaluation_context = EvaluationContext {
+            experiment_list,
+            context_map,
+            opt_in_variant_display_ids: vec!["30".to_string()],
+            ..Default::default()
+        };
+        let engine = QualificationEngine {
+            phases: vec![
+                Box::new(MappingPhase {
+                    mappers: vec![Box::new(OptInMapper), Box::new(VariantMapper)],
+                }),
+                Box::new(PrioritizationPhase {
+                    max_qualified_experiments: Some(2),
+                }),
+            ],
+            clock: Arc::new(SystemClock),
+        };
+        engine.qualify(&mut evaluation_context);
+        let experiment_ids: Vec<_> = evaluation_context
+            .experiment_list
+            .iter()
+            .map(|experiment| experiment.experiment_id)
+            .collect();
+        assert_eq!(experiment_ids, vec![1, 2, 3, 4]);
+        // Forced experiment 3 takes a slot, experiment 2 takes the other one
+        let demoted = &evaluation_context.result_by_phase["PrioritizationPhase"].variant_result_map;
+        let mut demoted_variant_ids: Vec<_> = demoted.keys().copied().collect();
+        demoted_variant_ids.sort();
+        assert_eq!(demoted_variant_ids, vec![1000, 1001, 4000, 4001]);
+        assert_eq!(
+            demoted[&1000],
+            QualificationResult::new(
+                QualificationResultType::NotQualified,
+                "Priority cap of 2 qualified experiments exceeded"
+            )
+        );
+    }
+
+    #[test]
+    fn result_packaged_phase_covers_every_variant() {
+        let mut context_map = color_context_map("77");
+        context_map.remove(UID_CONTEXT_KEY);
+        let mut targeted_out_experiment = layered_experiment(2, 2000);
+        targeted_out_experiment.layer = None;
+        targeted_out_experiment.context_expression = "EQ(SITEID, 0)".to_string();
+        let mut unallocated_experiment = layered_experiment(3, 3000);
+        unallocated_experiment.layer = None;
+        unallocated_experiment.base_mod = Traffic::try_from("0".repeat(100)).unwrap();
+        let mut evaluation_context = EvaluationContext {
+            experiment_list: vec![
+                color_experiment(),
+                targeted_out_experiment,
+                unallocated_experiment,
+            ],
+            context_map,
+            ..Default::default()
+        };
+        QualificationEngine::default().qualify(&mut evaluation_context);
+        let result = &evaluation_context.result.variant_result_map;
+        assert_eq!(result.len(), 6);
+        let qualified_count = result
+            .values()
+            .filter(|qualification_result| {
+                qualification_result.qualification_result_type == QualificationResultType::Qualified
+            })
+            .count();
+        assert_eq!(qualified_count, 1);
+        assert!(result.values().all(|qualification_result| {
+            qualification_result.qualification_result_type != QualificationResultType::Deferred
+        }));
+        assert_eq!(
+            result[&2001],
+            QualificationResult::new(
+                QualificationResultType::NotQualified,
+                "Context not matched: EQ(SITEID, 0) failed with SITEID=77"
+            )
+        );
+        let bucket = crate::hashing::bucket_of("0XF23AC", "search_88ax9i5", 100);
+        assert_eq!(
+            result[&3000],
+            QualificationResult::new(
+                QualificationResultType::NotQualified,
+                format!("Bucket {} outside base_mod", bucket)
+            )
+        );
+    }
+
+    #[test]
+    fn variant_mapper_reports_invalid_spectrum() {
+        let mut color_experiment = color_experiment();
+        color_experiment.variants[1].variant_mod = Traffic::try_from("01".repeat(49)).unwrap();
+        let mut evaluation_context = EvaluationContext {
+            experiment_list: vec![color_experiment],
+            context_map: color_context_map("77"),
+            ..Default::default()
+        };
+        VariantMapper.map(&mut evaluation_context);
+        let expected = QualificationResult::error(QualificationError::InvalidSpectrum {
+            experiment_id: 65536,
+            variant_id: Some(1025),
+            rule_id: None,
+            message: "spectrum must be 100 characters, found 98".to_string(),
+        });
+        assert_eq!(
+            expected.qualification_result_reason,
+            "Invalid spectrum for variant 1025: spectrum must be 100 characters, found 98"
+        );
+        assert_eq!(
+            evaluation_context.result_by_mapper["VariantMapper"].variant_result_map,
+            HashMap::from([(1024, expected.clone()), (1025, expected)])
+        );
+    }
+
+    #[test]
+    fn variant_mapper_respects_bucket_count() {
+        let mut color_experiment = color_experiment();
+        color_experiment.bucket_count = 1000;
+        let mut evaluation_context = EvaluationContext {
+            experiment_list: vec![color_experiment.clone()],
+            context_map: color_context_map("77"),
+            ..Default::default()
+        };
+        VariantMapper.map(&mut evaluation_context);
+        let expected = QualificationResult::error(QualificationError::InvalidSpectrum {
+            experiment_id: 65536,
+            variant_id: None,
+            rule_id: None,
+            message: "spectrum must be 1000 characters, found 100".to_string(),
+        });
+        assert_eq!(
+            evaluation_context.result_by_mapper["VariantMapper"].variant_result_map[&1024],
+            expected
+        );
+
+        // A 0.1% canary holding only the bucket of the unit out of 1000
+        let bucket = crate::hashing::bucket_of("0XF23AC", "search_88ax9i5", 1000);
+        let mut canary = crate::spectrum::Spectrum::empty(1000);
+        canary.insert(bucket as usize);
+        color_experiment.base_mod = Traffic::from(crate::spectrum::Spectrum::full(1000));
+        color_experiment.variants[1].variant_mod = Traffic::from(canary.complement());
+        color_experiment.variants[0].variant_mod = Traffic::from(canary);
+        let mut evaluation_context = EvaluationContext {
+            experiment_list: vec![color_experiment],
+            context_map: color_context_map("77"),
+            ..Default::default()
+        };
+        VariantMapper.map(&mut evaluation_context);
+        assert_eq!(
+            evaluation_context.result_by_mapper["VariantMapper"].variant_result_map,
+            HashMap::from([(
+                1024,
+                QualificationResult::new(
+                    QualificationResultType::Deferred,
+                    format!("Bucket {} in variant_mod", bucket)
+                )
+            )])
+        );
+    }
+
+    #[test]
+    fn variant_mapper_ramps_variant_traffic() {
+        let mut color_experiment = color_experiment();
+        color_experiment.variants[1].ramp = Some(RampSchedule {
+            steps: vec![
+                RampStep {
+                    at: 1_000,
+                    percent: 10.0,
+                },
+                RampStep {
+                    at: 2_000,
+                    percent: 30.0,
+                },
+            ],
+        });
+        let unit_values: Vec<_> = (0..300).map(|index| format!("guid_{}", index)).collect();
+        let units_at = |variant_id, now_millis| {
+            unit_values
+                .iter()
+                .filter(|unit_value| {
+                    let mut context_map = color_context_map("77");
+                    context_map.insert("LOOKUP_ID".to_string(), unit_value.to_string());
+                    let mut evaluation_context = EvaluationContext {
+                        experiment_list: vec![color_experiment.clone()],
+                        context_map,
+                        now_millis,
+                        ..Default::default()
+                    };
+                    VariantMapper.map(&mut evaluation_context);
+                    evaluation_context.result_by_mapper["VariantMapper"]
+                        .variant_result_map
+                        .get(&variant_id)
+                        .is_some_and(|qualification_result| {
+                            qualification_result.qualification_result_type
+                                == QualificationResultType::Deferred
+                        })
+                })
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+        let units_in_buckets = |buckets: std::ops::Range<u32>| {
+            unit_values
+                .iter()
+                .filter(|unit_value| {
+                    buckets.contains(&crate::hashing::bucket_of("0XF23AC", unit_value, 100))
+                })
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+        assert!(units_at(1025, 999).is_empty());
+        assert_eq!(units_at(1025, 1_000), units_in_buckets(50..60));
+        assert_eq!(units_at(1025, 2_000), units_in_buckets(50..80));
+        assert_eq!(units_at(1025, u64::MAX), units_in_buckets(50..80));
+        // Red is not ramped
+        assert_eq!(units_at(1024, 999), units_in_buckets(0..50));
+    }
+
+    #[test]
+    fn variant_rule_mapper_ramps_target_traffic() {
+        let mut color_experiment = color_experiment();
+        color_experiment.variant_rules[0].target.ramp_map = HashMap::from([(
+            1024,
+            RampSchedule {
+                steps: vec![RampStep {
+                    at: 1_000,
+                    percent: 50.0,
+                }],
+            },
+        )]);
+        let mut context_map = color_context_map("0");
+        context_map.insert("CHANNELID".to_string(), "1".to_string());
+        let bucket = crate::hashing::bucket_of("0XF23AC", "search_88ax9i5", 100);
+        for (now_millis, ramped_up) in [(999, false), (1_000, true)] {
+            let mut evaluation_context = EvaluationContext {
+                experiment_list: vec![color_experiment.clone()],
+                context_map: context_map.clone(),
+                now_millis,
+                ..Default::default()
+            };
+            VariantRuleMapper.map(&mut evaluation_context);
+            let expected = if bucket < 50 {
+                HashMap::from([(
+                    1025,
+                    QualificationResult::new(
+                        QualificationResultType::Deferred,
+                        format!("rule_id 0: Bucket {} in variant_mod", bucket),
+                    ),
+                )])
+            } else if ramped_up {
+                HashMap::from([(
+                    1024,
+                    QualificationResult::new(
+                        QualificationResultType::Deferred,
+                        format!("rule_id 0: Bucket {} in variant_mod", bucket),
+                    ),
+                )])
+            } else {
+                let unallocated = QualificationResult::new(
+                    QualificationResultType::NotQualified,
+                    format!("rule_id 0: Bucket {} not allocated to any variant", bucket),
+                );
+                HashMap::from([(1024, unallocated.clone()), (1025, unallocated)])
+            };
+            assert_eq!(
+                evaluation_context.result_by_mapper["VariantRuleMapper"].variant_result_map,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn validation_phase_isolates_missing_context_key() {
+        let mut guid_experiment = layered_experiment(2, 2000);
+        guid_experiment.layer = None;
+        guid_experiment.randomization_unit_key = "GUID".to_string();
+        let mut context_map = color_context_map("77");
+        context_map.remove(UID_CONTEXT_KEY);
+        let mut evaluation_context = EvaluationContext {
+            experiment_list: vec![guid_experiment, color_experiment()],
+            context_map,
+            error_mode: ErrorMode::IsolateExperiment,
+            ..Default::default()
+        };
+        QualificationEngine::default().qualify(&mut evaluation_context);
+        assert_eq!(evaluation_context.error, None);
+        let result = &evaluation_context.result.variant_result_map;
+        assert_eq!(result.len(), 4);
+        let expected = QualificationResult::error(QualificationError::MissingContextKey {
+            experiment_id: 2,
+            key: "GUID".to_string(),
+        });
+        assert_eq!(result[&2000], expected);
+        assert_eq!(result[&2001], expected);
+        let qualified_count = [1024, 1025]
+            .iter()
+            .filter(|variant_id| {
+                result[variant_id].qualification_result_type == QualificationResultType::Qualified
+            })
+            .count();
+        assert_eq!(qualified_count, 1);
     }
 }
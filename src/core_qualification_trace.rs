@@ -0,0 +1,239 @@
+use crate::core_qualification_dto::{
+    EvaluationContext, QualificationResult, QualificationResultType,
+};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+// Every Phase and Mapper run of one qualification, in execution order, recorded when
+// EvaluationContext::trace is set. A MappingPhase step is followed by the steps of its Mappers.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct DecisionTrace {
+    pub context: BTreeMap<String, String>,
+    pub opt_in_variant_display_ids: Vec<String>,
+    pub steps: Vec<TraceStep>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct TraceStep {
+    pub name: String,
+    pub kind: TraceStepKind,
+    // Experiments without a final result when the step started
+    pub undecided_experiment_ids: Vec<i32>,
+    // Variants whose result the step changed, in experiment_list order
+    pub changes: Vec<VariantChange>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum TraceStepKind {
+    Phase,
+    Mapper,
+}
+
+// before / after are None while no Phase or Mapper recorded a result for the variant
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct VariantChange {
+    pub experiment_id: i32,
+    pub variant_id: i32,
+    pub before: Option<QualificationResultType>,
+    pub after: Option<QualificationResultType>,
+    pub reason: String,
+}
+
+impl DecisionTrace {
+    pub fn new(context: &EvaluationContext) -> Self {
+        DecisionTrace {
+            context: context
+                .context_map
+                .iter()
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect(),
+            opt_in_variant_display_ids: context.opt_in_variant_display_ids.clone(),
+            steps: vec![],
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("decision traces always serialize to JSON")
+    }
+}
+
+impl EvaluationContext {
+    // Run one Phase or Mapper, recording what it changed when tracing
+    pub fn traced(&mut self, name: &str, kind: TraceStepKind, step: impl FnOnce(&mut Self)) {
+        if self.trace.is_none() {
+            step(self);
+            return;
+        }
+        let undecided_experiment_ids = self
+            .experiment_list
+            .iter()
+            .filter(|experiment| !self.is_experiment_decided(experiment))
+            .map(|experiment| experiment.experiment_id)
+            .collect();
+        // Keyed by variant_id, as a step may reorder experiment_list
+        let mut before: HashMap<_, _> = self
+            .variant_states()
+            .into_iter()
+            .map(|(_, variant_id, state)| (variant_id, state))
+            .collect();
+        // Steps recorded while this one runs (the Mappers of a MappingPhase) come after it
+        let index = self.trace.as_ref().map_or(0, |trace| trace.steps.len());
+        step(self);
+        let changes = self
+            .variant_states()
+            .into_iter()
+            .filter_map(|(experiment_id, variant_id, after)| {
+                let before = before.remove(&variant_id).flatten();
+                (before != after).then(|| VariantChange {
+                    experiment_id,
+                    variant_id,
+                    before: before.map(|result| result.qualification_result_type),
+                    after: after
+                        .as_ref()
+                        .map(|result| result.qualification_result_type),
+                    reason: after
+                        .map_or(String::new(), |result| result.qualification_result_reason),
+                })
+            })
+            .collect();
+        if let Some(trace) = &mut self.trace {
+            trace.steps.insert(
+                index,
+                TraceStep {
+                    name: name.to_string(),
+                    kind,
+                    undecided_experiment_ids,
+                    changes,
+                },
+            );
+        }
+    }
+
+    // The final result of every variant, or else its Mapper assignment
+    fn variant_states(&self) -> Vec<(i32, i32, Option<QualificationResult>)> {
+        self.experiment_list
+            .iter()
+            .flat_map(|experiment| {
+                experiment.variants.iter().map(|variant| {
+                    let state = self
+                        .result
+                        .variant_result_map
+                        .get(&variant.variant_id)
+                        .or_else(|| {
+                            self.result_by_mapper
+                                .values()
+                                .find_map(|evaluation_result| {
+                                    evaluation_result
+                                        .variant_result_map
+                                        .get(&variant.variant_id)
+                                })
+                        });
+                    (experiment.experiment_id, variant.variant_id, state.cloned())
+                })
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for DecisionTrace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let context: Vec<_> = self
+            .context
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, value))
+            .collect();
+        writeln!(f, "Context: {}", context.join(", "))?;
+        if !self.opt_in_variant_display_ids.is_empty() {
+            writeln!(f, "Opt-ins: {}", self.opt_in_variant_display_ids.join(", "))?;
+        }
+        let type_of = |result_type: Option<QualificationResultType>| {
+            result_type.map_or("-".to_string(), |result_type| format!("{:?}", result_type))
+        };
+        for step in &self.steps {
+            let indent = match step.kind {
+                TraceStepKind::Phase => "",
+                TraceStepKind::Mapper => "  ",
+            };
+            let undecided: Vec<_> = step
+                .undecided_experiment_ids
+                .iter()
+                .map(i32::to_string)
+                .collect();
+            write!(
+                f,
+                "{}{} (undecided: {})",
+                indent,
+                step.name,
+                undecided.join(", ")
+            )?;
+            if step.changes.is_empty() {
+                writeln!(f, ": no change")?;
+                continue;
+            }
+            writeln!(f)?;
+            for change in &step.changes {
+                writeln!(
+                    f,
+                    "{}  experiment {} variant {}: {} -> {} ({})",
+                    indent,
+                    change.experiment_id,
+                    change.variant_id,
+                    type_of(change.before),
+                    type_of(change.after),
+                    change.reason
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_trace_as_text() {
+        let trace = DecisionTrace {
+            context: BTreeMap::from([
+                ("SITEID".to_string(), "77".to_string()),
+                ("LOOKUP_ID".to_string(), "abc".to_string()),
+            ]),
+            opt_in_variant_display_ids: vec![],
+            steps: vec![
+                TraceStep {
+                    name: "MappingPhase".to_string(),
+                    kind: TraceStepKind::Phase,
+                    undecided_experiment_ids: vec![65536],
+                    changes: vec![VariantChange {
+                        experiment_id: 65536,
+                        variant_id: 1024,
+                        before: None,
+                        after: Some(QualificationResultType::Deferred),
+                        reason: "Bucket 38 in variant_mod".to_string(),
+                    }],
+                },
+                TraceStep {
+                    name: "OptInMapper".to_string(),
+                    kind: TraceStepKind::Mapper,
+                    undecided_experiment_ids: vec![65536],
+                    changes: vec![],
+                },
+            ],
+        };
+        assert_eq!(
+            trace.to_string(),
+            "\
+Context: LOOKUP_ID=abc, SITEID=77
+MappingPhase (undecided: 65536)
+  experiment 65536 variant 1024: - -> Deferred (Bucket 38 in variant_mod)
+  OptInMapper (undecided: 65536): no change
+"
+        );
+    }
+}
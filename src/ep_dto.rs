@@ -1,32 +1,82 @@
 // An attribute to hide warnings for unused code.
 #![allow(dead_code)]
 
+#[cfg(feature = "serde")]
+use crate::hashing::DEFAULT_BUCKET_COUNT;
+use crate::hashing::MAX_BUCKET_COUNT;
+use crate::spectrum::Spectrum;
 use std::collections::HashMap;
 
-const BASE_HASHING_CONSTANT: &str = "EXPT";
+pub const BASE_HASHING_CONSTANT: &str = "EXPT";
 
 // Experiment DTO
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(deny_unknown_fields))]
 pub struct Experiment {
     pub name: String,
+    #[cfg_attr(feature = "serde", serde(default))]
     pub context_expression: String,
+    #[cfg_attr(feature = "serde", serde(default))]
     pub hashing_constant: String,
     pub experiment_id: i32,
+    #[cfg_attr(feature = "serde", serde(default))]
     pub experiment_flags: i32,
+    #[cfg_attr(feature = "serde", serde(default))]
     pub variant_rules: Vec<VariantRule>,
     pub variants: Vec<Variant>,
     pub base_mod: Traffic,
     pub randomization_unit_key: String,
+    // Experiments sharing a layer are mutually exclusive: they bucket users with the same salt,
+    // partition the traffic through their base_mod and at most one of them qualifies per user
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub layer: Option<String>,
+    // Lower value is more important; ties keep the order of the experiment list
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub priority: i32,
+    // Resolution of the traffic split: base_mod and every variant spectrum hold one character
+    // per bucket, e.g. 1000 buckets allow 0.1% allocations
+    #[cfg_attr(feature = "serde", serde(default = "default_bucket_count"))]
+    pub bucket_count: u32,
+    // Active window in milliseconds since the Unix epoch, from start_time (inclusive) until
+    // end_time (exclusive); a missing bound leaves the window open on that side
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub start_time: Option<u64>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub end_time: Option<u64>,
+    // A paused experiment is not active, whatever its window
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub paused: bool,
+}
+
+#[cfg(feature = "serde")]
+fn default_bucket_count() -> u32 {
+    DEFAULT_BUCKET_COUNT
 }
 
 // Target under Feature Flag Variant Rule
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(deny_unknown_fields))]
 pub struct Target {
+    // Keyed by variant_id; YAML / TOML documents may write the keys as strings
+    #[cfg_attr(
+        feature = "serde",
+        serde(deserialize_with = "deserialize_variant_id_map")
+    )]
     pub variant_mod_map: HashMap<i32, Traffic>,
+    // Ramps of the spectrums of variant_mod_map, keyed by variant_id
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, deserialize_with = "deserialize_variant_id_map")
+    )]
+    pub ramp_map: HashMap<i32, RampSchedule>,
 }
 
 // Variant Rule for supporting Feature Flag
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(deny_unknown_fields))]
 pub struct VariantRule {
     pub rule_id: i32,
     pub context_expression: String,
@@ -34,21 +84,387 @@ pub struct VariantRule {
 }
 
 // Traffic Sample
-#[derive(Debug, PartialEq)]
+// Serialized as the bare spectrum string. Deserialized from a spectrum string or, for hand-authored
+// configs, from a map with one of { buckets: "0-49, 60" } or { percent: 50 }, over 100 buckets
+// unless the map also holds a bucket_count: { percent: 0.1, bucket_count: 1000 }. ep_loader
+// fills in the bucket_count of the map's experiment, and rejects a map holding another one.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(into = "String"))]
 pub struct Traffic {
-    pub spectrum: String,
+    spectrum: Spectrum,
+}
+
+impl Traffic {
+    // Allocated buckets, in place of the former public spectrum string field: the string is
+    // spectrum().to_string(), and Traffic::try_from parses one back
+    pub fn spectrum(&self) -> &Spectrum {
+        &self.spectrum
+    }
+
+    // A usable spectrum holds exactly one bucket per bucket of its experiment
+    pub fn validate(&self, bucket_count: u32) -> Result<(), String> {
+        check_bucket_count(bucket_count)?;
+        if self.spectrum.len() != bucket_count as usize {
+            return Err(format!(
+                "spectrum must be {} characters, found {}",
+                bucket_count,
+                self.spectrum.len()
+            ));
+        }
+        Ok(())
+    }
+
+    // Traffic from inclusive bucket ranges such as "0-49" or "0-9, 50, 90-99" (0 is the leftmost bucket)
+    pub fn from_buckets(buckets: &str, bucket_count: u32) -> Result<Traffic, String> {
+        check_bucket_count(bucket_count)?;
+        let mut spectrum = Spectrum::empty(bucket_count as usize);
+        for range in buckets
+            .split(',')
+            .map(str::trim)
+            .filter(|range| !range.is_empty())
+        {
+            let parse_bucket = |bucket: &str| {
+                bucket
+                    .trim()
+                    .parse::<u32>()
+                    .ok()
+                    .filter(|bucket| *bucket < bucket_count)
+                    .ok_or_else(|| {
+                        format!(
+                            "bucket range {} must hold buckets between 0 and {}",
+                            range,
+                            bucket_count.saturating_sub(1)
+                        )
+                    })
+            };
+            let (first, last) = match range.split_once('-') {
+                Some((first, last)) => (parse_bucket(first)?, parse_bucket(last)?),
+                None => (parse_bucket(range)?, parse_bucket(range)?),
+            };
+            if first > last {
+                return Err(format!("bucket range {} is reversed", range));
+            }
+            (first..=last).for_each(|bucket| spectrum.insert(bucket as usize));
+        }
+        Ok(Traffic { spectrum })
+    }
+
+    // Traffic holding the leading buckets worth the given percent of the traffic
+    pub fn from_percent(percent: f64, bucket_count: u32) -> Result<Traffic, String> {
+        check_bucket_count(bucket_count)?;
+        // Percents like 0.07 are not exact binary fractions, so accept the nearest whole bucket
+        let exact_buckets = percent * bucket_count as f64 / 100.0;
+        let buckets = exact_buckets.round();
+        let whole_buckets = (exact_buckets - buckets).abs() < 1e-6;
+        if !whole_buckets || !(0.0..=bucket_count as f64).contains(&buckets) {
+            return Err(format!(
+                "percent {} must be between 0 and 100 and a whole number of the {} buckets",
+                percent, bucket_count
+            ));
+        }
+        let mut spectrum = Spectrum::empty(bucket_count as usize);
+        (0..buckets as usize).for_each(|bucket| spectrum.insert(bucket));
+        Ok(Traffic { spectrum })
+    }
+
+    // Allocated buckets as inclusive ranges, the inverse of from_buckets
+    pub fn to_buckets(&self) -> String {
+        let mut ranges = vec![];
+        let mut first_allocated = None;
+        for bucket in 0..=self.spectrum.len() as u32 {
+            match (self.contains_bucket(bucket), first_allocated) {
+                (true, None) => first_allocated = Some(bucket),
+                (false, Some(first)) => {
+                    ranges.push(if first == bucket - 1 {
+                        first.to_string()
+                    } else {
+                        format!("{}-{}", first, bucket - 1)
+                    });
+                    first_allocated = None;
+                }
+                _ => {}
+            }
+        }
+        ranges.join(", ")
+    }
+
+    // Whether the bucket (0 is the leftmost character) is allocated in the spectrum
+    pub fn contains_bucket(&self, bucket: u32) -> bool {
+        self.spectrum.contains(bucket as usize)
+    }
+}
+
+fn check_bucket_count(bucket_count: u32) -> Result<(), String> {
+    if !(1..=MAX_BUCKET_COUNT).contains(&bucket_count) {
+        return Err(format!(
+            "bucket_count must be between 1 and {}, found {}",
+            MAX_BUCKET_COUNT, bucket_count
+        ));
+    }
+    Ok(())
+}
+
+// Any length is accepted, the spectrum is checked against its experiment's bucket_count on use
+impl TryFrom<&str> for Traffic {
+    type Error = String;
+
+    fn try_from(spectrum: &str) -> Result<Self, Self::Error> {
+        Ok(Traffic {
+            spectrum: spectrum.parse()?,
+        })
+    }
+}
+
+impl TryFrom<String> for Traffic {
+    type Error = String;
+
+    fn try_from(spectrum: String) -> Result<Self, Self::Error> {
+        Traffic::try_from(spectrum.as_str())
+    }
+}
+
+impl From<Spectrum> for Traffic {
+    fn from(spectrum: Spectrum) -> Self {
+        Traffic { spectrum }
+    }
+}
+
+impl From<Traffic> for String {
+    fn from(traffic: Traffic) -> Self {
+        traffic.spectrum.to_string()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Traffic {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct TrafficVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for TrafficVisitor {
+            type Value = Traffic;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str("a spectrum string or a map with one of buckets, percent")
+            }
+
+            fn visit_str<E: serde::de::Error>(self, spectrum: &str) -> Result<Traffic, E> {
+                Traffic::try_from(spectrum).map_err(E::custom)
+            }
+
+            fn visit_map<A: serde::de::MapAccess<'de>>(
+                self,
+                mut map: A,
+            ) -> Result<Traffic, A::Error> {
+                use serde::de::Error;
+                let mut buckets: Option<String> = None;
+                let mut percent: Option<f64> = None;
+                let mut bucket_count = DEFAULT_BUCKET_COUNT;
+                while let Some(key) = map.next_key::<String>()? {
+                    match key.as_str() {
+                        "buckets" if buckets.is_none() && percent.is_none() => {
+                            buckets = Some(map.next_value()?)
+                        }
+                        "percent" if buckets.is_none() && percent.is_none() => {
+                            percent = Some(map.next_value()?)
+                        }
+                        "buckets" | "percent" => {
+                            return Err(A::Error::custom("expected only one of buckets, percent"));
+                        }
+                        "bucket_count" => bucket_count = map.next_value()?,
+                        other => {
+                            return Err(A::Error::unknown_field(
+                                other,
+                                &["buckets", "percent", "bucket_count"],
+                            ));
+                        }
+                    }
+                }
+                match (buckets, percent) {
+                    (Some(buckets), _) => Traffic::from_buckets(&buckets, bucket_count),
+                    (_, Some(percent)) => Traffic::from_percent(percent, bucket_count),
+                    (None, None) => Err("expected one of buckets, percent".to_string()),
+                }
+                .map_err(A::Error::custom)
+            }
+        }
+
+        deserializer.deserialize_any(TrafficVisitor)
+    }
+}
+
+#[cfg(feature = "serde")]
+fn deserialize_variant_id_map<'de, D, T>(deserializer: D) -> Result<HashMap<i32, T>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: serde::Deserialize<'de>,
+{
+    #[derive(PartialEq, Eq, Hash)]
+    struct VariantId(i32);
+
+    impl<'de> serde::Deserialize<'de> for VariantId {
+        fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            struct VariantIdVisitor;
+
+            impl serde::de::Visitor<'_> for VariantIdVisitor {
+                type Value = VariantId;
+
+                fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                    formatter.write_str("a variant_id")
+                }
+
+                fn visit_i64<E: serde::de::Error>(self, variant_id: i64) -> Result<VariantId, E> {
+                    i32::try_from(variant_id)
+                        .map(VariantId)
+                        .map_err(|_| E::custom(format!("variant_id {} out of range", variant_id)))
+                }
+
+                fn visit_u64<E: serde::de::Error>(self, variant_id: u64) -> Result<VariantId, E> {
+                    i32::try_from(variant_id)
+                        .map(VariantId)
+                        .map_err(|_| E::custom(format!("variant_id {} out of range", variant_id)))
+                }
+
+                fn visit_str<E: serde::de::Error>(self, variant_id: &str) -> Result<VariantId, E> {
+                    variant_id
+                        .parse()
+                        .map(VariantId)
+                        .map_err(|_| E::custom(format!("invalid variant_id {}", variant_id)))
+                }
+            }
+
+            deserializer.deserialize_any(VariantIdVisitor)
+        }
+    }
+
+    let variant_id_map: HashMap<VariantId, T> = serde::Deserialize::deserialize(deserializer)?;
+    Ok(variant_id_map
+        .into_iter()
+        .map(|(VariantId(variant_id), value)| (variant_id, value))
+        .collect())
+}
+
+// Gradual rollout of a spectrum: from each step's time on, the variant receives the step's percent
+// of the experiment's traffic, taken from the allocated buckets of its spectrum from the leftmost
+// one and rounded down to whole buckets. Only the highest percent reached so far counts, so the
+// allocation never shrinks and users already exposed stay exposed; before the first step it is empty.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct RampSchedule {
+    pub steps: Vec<RampStep>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(deny_unknown_fields))]
+pub struct RampStep {
+    // Milliseconds since the Unix epoch
+    pub at: u64,
+    pub percent: f64,
+}
+
+impl RampSchedule {
+    // Steps are ordered by time, with growing percents between 0 and 100
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(step) = self
+            .steps
+            .iter()
+            .find(|step| !(0.0..=100.0).contains(&step.percent))
+        {
+            return Err(format!(
+                "percent {} must be between 0 and 100",
+                step.percent
+            ));
+        }
+        for pair in self.steps.windows(2) {
+            if pair[1].at < pair[0].at {
+                return Err(format!(
+                    "step at {} precedes step at {}",
+                    pair[1].at, pair[0].at
+                ));
+            }
+            if pair[1].percent < pair[0].percent {
+                return Err(format!(
+                    "percent {} at {} is below percent {} at {}, a ramp never shrinks",
+                    pair[1].percent, pair[1].at, pair[0].percent, pair[0].at
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn percent_at(&self, now_millis: u64) -> f64 {
+        self.steps
+            .iter()
+            .filter(|step| step.at <= now_millis)
+            .map(|step| step.percent)
+            .fold(0.0, f64::max)
+    }
+
+    // The part of traffic allocated at now_millis, out of an experiment of bucket_count buckets
+    pub fn ramp(&self, traffic: &Traffic, bucket_count: u32, now_millis: u64) -> Traffic {
+        let buckets = (self.percent_at(now_millis) * bucket_count as f64 / 100.0).floor() as usize;
+        let mut spectrum = Spectrum::empty(traffic.spectrum.len());
+        traffic
+            .spectrum
+            .buckets()
+            .take(buckets)
+            .for_each(|bucket| spectrum.insert(bucket));
+        Traffic { spectrum }
+    }
 }
 
 // Variant DTO
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(deny_unknown_fields))]
 pub struct Variant {
     pub name: String,
     pub value: String,
     pub variant_id: i32,
     pub variant_display_id: String,
+    #[cfg_attr(feature = "serde", serde(default))]
     pub variant_flags: i32,
     pub variant_mod: Traffic,
+    #[cfg_attr(feature = "serde", serde(default))]
     pub whitelisted_uids: Vec<String>,
+    // Ramp of variant_mod, which then holds the buckets the variant ends up with
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub ramp: Option<RampSchedule>,
+}
+
+// Holdout DTO
+// Users whose bucket is in traffic never qualify for an experiment of the scope, which measures the
+// cumulative impact of its experiments. Users are bucketed on their own randomization_unit_key
+// whatever the unit of each experiment; users without it in the context are not held out.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(deny_unknown_fields))]
+pub struct Holdout {
+    pub name: String,
+    pub holdout_id: i32,
+    // Scope: the experiments of this layer, or every experiment when None
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub layer: Option<String>,
+    pub randomization_unit_key: String,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub hashing_constant: String,
+    // The held out buckets
+    pub traffic: Traffic,
+    #[cfg_attr(feature = "serde", serde(default = "default_bucket_count"))]
+    pub bucket_count: u32,
+}
+
+impl Holdout {
+    // A usable holdout's traffic holds one bucket per bucket_count
+    pub fn validate(&self) -> Result<(), String> {
+        self.traffic.validate(self.bucket_count)
+    }
+
+    pub fn covers(&self, experiment: &Experiment) -> bool {
+        self.layer.is_none() || self.layer == experiment.layer
+    }
 }
 
 // Unit tests for DTO creation
@@ -70,8 +486,9 @@ mod tests {
             variant_id: 1024,
             variant_display_id: "0aX0".to_string(),
             variant_flags: 0,
-            variant_mod: Traffic { spectrum: "1111111111111111111111111111111111111111111111111100000000000000000000000000000000000000000000000000".to_string() },
-            whitelisted_uids: vec!["1038812".to_string()]
+            variant_mod: Traffic::try_from("1111111111111111111111111111111111111111111111111100000000000000000000000000000000000000000000000000".to_string()).unwrap(),
+            whitelisted_uids: vec!["1038812".to_string()],
+            ramp: None,
         };
         let color_blue_variant = Variant {
             name: "Blue Variant".to_string(),
@@ -79,17 +496,19 @@ mod tests {
             variant_id: 1025,
             variant_display_id: "0aX1".to_string(),
             variant_flags: 0,
-            variant_mod: Traffic { spectrum: "0000000000000000000000000000000000000000000000000011111111111111111111111111111111111111111111111111".to_string() },
-            whitelisted_uids: vec!["1015529".to_string()]
+            variant_mod: Traffic::try_from("0000000000000000000000000000000000000000000000000011111111111111111111111111111111111111111111111111".to_string()).unwrap(),
+            whitelisted_uids: vec!["1015529".to_string()],
+            ramp: None,
         };
         let mut first_variant_mod_map = HashMap::new();
-        first_variant_mod_map.insert(1024, Traffic { spectrum: "0000000000000000000000000000000000000000000000000011111111111111111111111111111111111111111111111111".to_string() });
-        first_variant_mod_map.insert(1025, Traffic { spectrum: "1111111111111111111111111111111111111111111111111100000000000000000000000000000000000000000000000000".to_string() });
+        first_variant_mod_map.insert(1024, Traffic::try_from("0000000000000000000000000000000000000000000000000011111111111111111111111111111111111111111111111111".to_string()).unwrap());
+        first_variant_mod_map.insert(1025, Traffic::try_from("1111111111111111111111111111111111111111111111111100000000000000000000000000000000000000000000000000".to_string()).unwrap());
         let first_variant_rule = VariantRule {
             rule_id: 0,
             context_expression: "AND(IN(SITEID, 0), IN(CHANNELID, 1))".to_string(),
             target: Target {
                 variant_mod_map: first_variant_mod_map,
+                ramp_map: HashMap::new(),
             },
         };
         let color_experiment = Experiment {
@@ -100,12 +519,122 @@ mod tests {
             experiment_flags: 0,
             variant_rules: vec![first_variant_rule],
             variants: vec![color_red_variant, color_blue_variant],
-            base_mod: Traffic { spectrum: "1111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111".to_string() },
+            base_mod: Traffic::try_from("1111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111".to_string()).unwrap(),
             randomization_unit_key: "LOOKUP_ID".to_string(),
+            layer: None,
+            priority: 0,
+            bucket_count: 100,
+            start_time: None,
+            end_time: None,
+            paused: false,
         };
         assert_eq!(color_experiment.name, "Color Experiment");
         assert_eq!(color_experiment.variants.len(), 2);
         assert_eq!(color_experiment.variant_rules.len(), 1);
         assert_eq!(color_experiment, color_experiment);
     }
+
+    #[test]
+    fn traffic_notations() {
+        let traffic = Traffic::from_buckets("0-49", 100).unwrap();
+        assert_eq!(
+            traffic.spectrum().to_string(),
+            "1".repeat(50) + &"0".repeat(50)
+        );
+        assert_eq!(traffic.spectrum().percent(), 50.0);
+        assert_eq!(traffic, Traffic::from_percent(50.0, 100).unwrap());
+        assert_eq!(traffic.to_buckets(), "0-49");
+        assert_eq!(
+            Traffic::try_from(traffic.spectrum().to_string().as_str()),
+            Ok(traffic.clone())
+        );
+        assert!(Traffic::try_from("01x").is_err());
+
+        let traffic = Traffic::from_buckets(" 0-9, 50,90-99 ", 100).unwrap();
+        assert_eq!(traffic.to_buckets(), "0-9, 50, 90-99");
+        assert_eq!(
+            Traffic::from_buckets(&traffic.to_buckets(), 100).unwrap(),
+            traffic
+        );
+        assert_eq!(Traffic::from_buckets("", 100).unwrap().to_buckets(), "");
+
+        assert_eq!(
+            Traffic::from_buckets("10-100", 100).unwrap_err(),
+            "bucket range 10-100 must hold buckets between 0 and 99"
+        );
+        assert_eq!(
+            Traffic::from_buckets("20-10", 100).unwrap_err(),
+            "bucket range 20-10 is reversed"
+        );
+        assert!(Traffic::from_percent(0.5, 100).is_err());
+        assert!(Traffic::from_percent(101.0, 100).is_err());
+        assert_eq!(
+            Traffic::from_percent(0.07, 10000).unwrap(),
+            Traffic::from_buckets("0-6", 10000).unwrap()
+        );
+
+        let canary = Traffic::from_percent(0.1, 1000).unwrap();
+        assert_eq!(canary.spectrum().len(), 1000);
+        assert_eq!(canary.to_buckets(), "0");
+        assert_eq!(canary, Traffic::from_buckets("0", 1000).unwrap());
+        assert_eq!(canary.validate(1000), Ok(()));
+        assert_eq!(
+            canary.validate(100),
+            Err("spectrum must be 100 characters, found 1000".to_string())
+        );
+        assert_eq!(
+            Traffic::from_percent(50.0, 4_000_000_000).unwrap_err(),
+            "bucket_count must be between 1 and 1000000, found 4000000000"
+        );
+        assert!(Traffic::from_buckets("0", 0).is_err());
+        assert!(canary.validate(MAX_BUCKET_COUNT + 1).is_err());
+    }
+
+    #[test]
+    fn ramp_schedule_only_grows() {
+        let ramp_schedule = RampSchedule {
+            steps: vec![
+                RampStep {
+                    at: 1_000,
+                    percent: 1.0,
+                },
+                RampStep {
+                    at: 2_000,
+                    percent: 5.0,
+                },
+                RampStep {
+                    at: 3_000,
+                    percent: 25.0,
+                },
+            ],
+        };
+        assert_eq!(ramp_schedule.validate(), Ok(()));
+        let traffic = Traffic::from_buckets("10-19, 50-99", 100).unwrap();
+        let ramped = |now_millis| ramp_schedule.ramp(&traffic, 100, now_millis).to_buckets();
+        assert_eq!(ramped(999), "");
+        assert_eq!(ramped(1_000), "10");
+        assert_eq!(ramped(2_500), "10-14");
+        // Capped by the spectrum being ramped
+        assert_eq!(ramped(3_000), "10-19, 50-64");
+        assert_eq!(ramp_schedule.percent_at(u64::MAX), 25.0);
+
+        let mut shrinking = ramp_schedule.clone();
+        shrinking.steps.push(RampStep {
+            at: 4_000,
+            percent: 10.0,
+        });
+        assert_eq!(
+            shrinking.validate(),
+            Err("percent 10 at 4000 is below percent 25 at 3000, a ramp never shrinks".to_string())
+        );
+        assert_eq!(shrinking.percent_at(4_000), 25.0);
+        shrinking.steps[3] = RampStep {
+            at: 500,
+            percent: 100.0,
+        };
+        assert_eq!(
+            shrinking.validate(),
+            Err("step at 500 precedes step at 3000".to_string())
+        );
+    }
 }
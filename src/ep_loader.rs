@@ -0,0 +1,768 @@
+use crate::ep_dto::Experiment;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::Path;
+
+// Version of the experiment document layout understood by this crate
+pub const SCHEMA_VERSION: u32 = 1;
+
+// Experiment document shipped by the control plane:
+//   { "schema_version": 1, "experiments": [ { "name": ..., "base_mod": "1111...", ... } ] }
+// Hand-authored YAML / TOML documents share the layout and may write traffic as
+// { buckets: "0-49" } or { percent: 50 } instead of a spectrum. Such a map is over the
+// bucket_count of its experiment; a map which repeats a bucket_count must repeat the same one.
+// bucket_count is at most MAX_BUCKET_COUNT.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ExperimentDocument {
+    pub schema_version: u32,
+    pub experiments: Vec<Experiment>,
+}
+
+// Only schema_version is looked at before the document layout is known
+#[derive(Deserialize)]
+struct SchemaProbe {
+    schema_version: Option<u32>,
+}
+
+#[derive(Serialize)]
+struct ExperimentDocumentRef<'a> {
+    schema_version: u32,
+    experiments: &'a [Experiment],
+}
+
+// Failure to load an experiment document; field errors carry the path of the offending field,
+// e.g. experiments[0].variants[1].variant_mod
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigError {
+    Io {
+        path: String,
+        message: String,
+    },
+    Syntax {
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    MissingSchemaVersion,
+    UnsupportedSchemaVersion {
+        found: u32,
+        supported: u32,
+    },
+    Field {
+        path: String,
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    // The file extension names a format whose cargo feature is disabled
+    UnsupportedFormat {
+        path: String,
+    },
+    // A { buckets } / { percent } traffic map over other buckets than its experiment
+    TrafficBucketCount {
+        path: String,
+        bucket_count: u32,
+        experiment_bucket_count: u32,
+    },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io { path, message } => write!(f, "Cannot read {}: {}", path, message),
+            ConfigError::Syntax {
+                line,
+                column,
+                message,
+            } => write!(
+                f,
+                "Invalid document at line {} column {}: {}",
+                line, column, message
+            ),
+            ConfigError::MissingSchemaVersion => write!(f, "Missing field schema_version"),
+            ConfigError::UnsupportedSchemaVersion { found, supported } => write!(
+                f,
+                "Unsupported schema_version {}, expected {}",
+                found, supported
+            ),
+            ConfigError::Field {
+                path,
+                line,
+                column,
+                message,
+            } => write!(
+                f,
+                "Invalid field {} at line {} column {}: {}",
+                path, line, column, message
+            ),
+            ConfigError::UnsupportedFormat { path } => {
+                write!(f, "Unsupported document format of {}", path)
+            }
+            ConfigError::TrafficBucketCount {
+                path,
+                bucket_count,
+                experiment_bucket_count,
+            } => write!(
+                f,
+                "Invalid field {}: traffic over {} buckets in an experiment of bucket_count {}",
+                path, bucket_count, experiment_bucket_count
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+pub fn load_experiments_from_json(json: &str) -> Result<Vec<Experiment>, ConfigError> {
+    load_document(deserialize_json(json)?, || deserialize_json(json))
+}
+
+#[cfg(feature = "yaml")]
+pub fn load_experiments_from_yaml(yaml: &str) -> Result<Vec<Experiment>, ConfigError> {
+    load_document(deserialize_yaml(yaml)?, || deserialize_yaml(yaml))
+}
+
+#[cfg(feature = "toml")]
+pub fn load_experiments_from_toml(toml: &str) -> Result<Vec<Experiment>, ConfigError> {
+    load_document(deserialize_toml(toml)?, || deserialize_toml(toml))
+}
+
+// The format follows the file extension: .yaml / .yml, .toml, anything else is JSON
+pub fn load_experiments_from_file(path: impl AsRef<Path>) -> Result<Vec<Experiment>, ConfigError> {
+    let path = path.as_ref();
+    let content = std::fs::read_to_string(path).map_err(|io_error| ConfigError::Io {
+        path: path.display().to_string(),
+        message: io_error.to_string(),
+    })?;
+    match path.extension().and_then(|extension| extension.to_str()) {
+        #[cfg(feature = "yaml")]
+        Some("yaml" | "yml") => load_experiments_from_yaml(&content),
+        #[cfg(feature = "toml")]
+        Some("toml") => load_experiments_from_toml(&content),
+        #[cfg(not(feature = "yaml"))]
+        Some("yaml" | "yml") => Err(ConfigError::UnsupportedFormat {
+            path: path.display().to_string(),
+        }),
+        #[cfg(not(feature = "toml"))]
+        Some("toml") => Err(ConfigError::UnsupportedFormat {
+            path: path.display().to_string(),
+        }),
+        _ => load_experiments_from_json(&content),
+    }
+}
+
+// Pretty printed document of the current SCHEMA_VERSION
+pub fn experiments_to_json(experiments: &[Experiment]) -> String {
+    let document = ExperimentDocumentRef {
+        schema_version: SCHEMA_VERSION,
+        experiments,
+    };
+    serde_json::to_string_pretty(&document).expect("experiments always serialize to JSON")
+}
+
+// Document of the current SCHEMA_VERSION with traffic written as bucket ranges
+#[cfg(feature = "yaml")]
+pub fn experiments_to_yaml(experiments: &[Experiment]) -> String {
+    serde_yaml::to_string(&hand_authored_document(experiments))
+        .expect("experiments always serialize to YAML")
+}
+
+// Document of the current SCHEMA_VERSION with traffic written as bucket ranges
+#[cfg(feature = "toml")]
+pub fn experiments_to_toml(experiments: &[Experiment]) -> String {
+    toml::to_string_pretty(&hand_authored_document(experiments))
+        .expect("experiments always serialize to TOML")
+}
+
+// The document is parsed once into a Value, on which the schema is checked and traffic maps are
+// given their experiment's bucket_count before it is read as the typed document; locate parses
+// the text again only to place a field error
+fn load_document(
+    mut document: serde_json::Value,
+    locate: impl Fn() -> Result<ExperimentDocument, ConfigError>,
+) -> Result<Vec<Experiment>, ConfigError> {
+    check_schema_version(from_value(&document, &locate)?)?;
+    inherit_bucket_counts(&mut document)?;
+    Ok(from_value::<ExperimentDocument>(&document, &locate)?.experiments)
+}
+
+// A Value holds no positions, so an error is placed where the text reports the same path,
+// or at line 0 column 0 when it does not
+fn from_value<T: serde::de::DeserializeOwned>(
+    document: &serde_json::Value,
+    locate: &impl Fn() -> Result<ExperimentDocument, ConfigError>,
+) -> Result<T, ConfigError> {
+    serde_path_to_error::deserialize(document).map_err(|error| {
+        let path = error.path().to_string();
+        let message = strip_position(error.inner());
+        let (line, column) = match locate() {
+            Err(ConfigError::Field {
+                path: located,
+                line,
+                column,
+                ..
+            }) if located == path => (line, column),
+            Err(ConfigError::Syntax { line, column, .. }) if path == "." => (line, column),
+            _ => (0, 0),
+        };
+        if path != "." {
+            ConfigError::Field {
+                path,
+                line,
+                column,
+                message,
+            }
+        } else {
+            ConfigError::Syntax {
+                line,
+                column,
+                message,
+            }
+        }
+    })
+}
+
+fn check_schema_version(probe: SchemaProbe) -> Result<(), ConfigError> {
+    match probe.schema_version {
+        None => Err(ConfigError::MissingSchemaVersion),
+        Some(SCHEMA_VERSION) => Ok(()),
+        Some(found) => Err(ConfigError::UnsupportedSchemaVersion {
+            found,
+            supported: SCHEMA_VERSION,
+        }),
+    }
+}
+
+// A traffic map is deserialized on its own, so the bucket_count of its experiment is written
+// into it first; spectrum strings are checked against it on use. Values of the wrong type are
+// left to the typed document to report.
+fn inherit_bucket_counts(document: &mut serde_json::Value) -> Result<(), ConfigError> {
+    let experiments = document
+        .get_mut("experiments")
+        .and_then(serde_json::Value::as_array_mut);
+    for (index, experiment) in experiments.into_iter().flatten().enumerate() {
+        let path = format!("experiments[{}]", index);
+        let experiment_bucket_count = experiment["bucket_count"]
+            .as_u64()
+            .unwrap_or(crate::hashing::DEFAULT_BUCKET_COUNT as u64);
+        if !(1..=crate::hashing::MAX_BUCKET_COUNT as u64).contains(&experiment_bucket_count) {
+            return Err(ConfigError::Field {
+                path: format!("{}.bucket_count", path),
+                line: 0,
+                column: 0,
+                message: format!(
+                    "bucket_count must be between 1 and {}, found {}",
+                    crate::hashing::MAX_BUCKET_COUNT,
+                    experiment_bucket_count
+                ),
+            });
+        }
+        let inherit = |path: String, traffic: &mut serde_json::Value| {
+            let Some(traffic) = traffic.as_object_mut() else {
+                return Ok(());
+            };
+            match traffic.get("bucket_count").map(serde_json::Value::as_u64) {
+                None => {
+                    traffic.insert("bucket_count".to_string(), experiment_bucket_count.into());
+                    Ok(())
+                }
+                Some(Some(bucket_count)) if bucket_count != experiment_bucket_count => {
+                    Err(ConfigError::TrafficBucketCount {
+                        path,
+                        bucket_count: bucket_count.try_into().unwrap_or(u32::MAX),
+                        experiment_bucket_count: experiment_bucket_count as u32,
+                    })
+                }
+                Some(_) => Ok(()),
+            }
+        };
+        if let Some(traffic) = experiment.get_mut("base_mod") {
+            inherit(format!("{}.base_mod", path), traffic)?;
+        }
+        let variants = experiment
+            .get_mut("variants")
+            .and_then(serde_json::Value::as_array_mut);
+        for (variant_index, variant) in variants.into_iter().flatten().enumerate() {
+            if let Some(traffic) = variant.get_mut("variant_mod") {
+                inherit(
+                    format!("{}.variants[{}].variant_mod", path, variant_index),
+                    traffic,
+                )?;
+            }
+        }
+        let variant_rules = experiment
+            .get_mut("variant_rules")
+            .and_then(serde_json::Value::as_array_mut);
+        for (rule_index, variant_rule) in variant_rules.into_iter().flatten().enumerate() {
+            let variant_mod_map = variant_rule
+                .pointer_mut("/target/variant_mod_map")
+                .and_then(serde_json::Value::as_object_mut);
+            for (variant_id, traffic) in variant_mod_map.into_iter().flatten() {
+                inherit(
+                    format!(
+                        "{}.variant_rules[{}].target.variant_mod_map.{}",
+                        path, rule_index, variant_id
+                    ),
+                    traffic,
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// The serialized document with every spectrum of its experiment's bucket_count replaced by
+// { buckets: ... } and absent optional fields dropped, as TOML has no null
+#[cfg(any(feature = "yaml", feature = "toml"))]
+fn hand_authored_document(experiments: &[Experiment]) -> serde_json::Value {
+    fn buckets_of(traffic: &mut serde_json::Value, experiment_bucket_count: u64) {
+        if let Some(Ok(spectrum)) = traffic.as_str().map(str::parse::<crate::Spectrum>)
+            && spectrum.len() as u64 == experiment_bucket_count
+        {
+            let buckets = crate::ep_dto::Traffic::from(spectrum).to_buckets();
+            *traffic = serde_json::json!({ "buckets": buckets });
+        }
+    }
+
+    fn rewrite(value: &mut serde_json::Value, bucket_count: u64) {
+        match value {
+            serde_json::Value::Object(fields) => {
+                fields.retain(|_, field| !field.is_null());
+                let bucket_count = fields
+                    .get("bucket_count")
+                    .and_then(serde_json::Value::as_u64)
+                    .unwrap_or(bucket_count);
+                for (key, field) in fields.iter_mut() {
+                    match key.as_str() {
+                        "base_mod" | "variant_mod" => buckets_of(field, bucket_count),
+                        "variant_mod_map" => field
+                            .as_object_mut()
+                            .into_iter()
+                            .flat_map(|variant_mod_map| variant_mod_map.values_mut())
+                            .for_each(|traffic| buckets_of(traffic, bucket_count)),
+                        _ => rewrite(field, bucket_count),
+                    }
+                }
+            }
+            serde_json::Value::Array(items) => items
+                .iter_mut()
+                .for_each(|item| rewrite(item, bucket_count)),
+            _ => {}
+        }
+    }
+
+    let mut document = serde_json::to_value(ExperimentDocumentRef {
+        schema_version: SCHEMA_VERSION,
+        experiments,
+    })
+    .expect("experiments always serialize to JSON");
+    rewrite(&mut document, crate::hashing::DEFAULT_BUCKET_COUNT as u64);
+    document
+}
+
+fn deserialize_json<'de, T: Deserialize<'de>>(json: &'de str) -> Result<T, ConfigError> {
+    let mut deserializer = serde_json::Deserializer::from_str(json);
+    serde_path_to_error::deserialize(&mut deserializer).map_err(|error| {
+        let path = error.path().to_string();
+        let inner = error.into_inner();
+        if inner.is_data() && path != "." {
+            ConfigError::Field {
+                path,
+                line: inner.line(),
+                column: inner.column(),
+                message: strip_position(&inner),
+            }
+        } else {
+            ConfigError::Syntax {
+                line: inner.line(),
+                column: inner.column(),
+                message: strip_position(&inner),
+            }
+        }
+    })
+}
+
+#[cfg(feature = "yaml")]
+fn deserialize_yaml<T: serde::de::DeserializeOwned>(yaml: &str) -> Result<T, ConfigError> {
+    serde_path_to_error::deserialize(serde_yaml::Deserializer::from_str(yaml)).map_err(|error| {
+        let path = error.path().to_string();
+        let inner = error.into_inner();
+        let (line, column) = inner
+            .location()
+            .map_or((0, 0), |location| (location.line(), location.column()));
+        // serde_yaml wraps its messages as "<path>: <message> at line L column C"
+        let message = inner.to_string();
+        let message = message
+            .split_once(" at line ")
+            .map_or(message.as_str(), |(message, _)| message);
+        let message = message
+            .split_once(": ")
+            .filter(|(prefix, _)| !prefix.contains(' '))
+            .map_or(message, |(_, message)| message)
+            .to_string();
+        if path != "." {
+            ConfigError::Field {
+                path,
+                line,
+                column,
+                message,
+            }
+        } else {
+            ConfigError::Syntax {
+                line,
+                column,
+                message,
+            }
+        }
+    })
+}
+
+#[cfg(feature = "toml")]
+fn deserialize_toml<T: serde::de::DeserializeOwned>(toml: &str) -> Result<T, ConfigError> {
+    serde_path_to_error::deserialize(toml::Deserializer::new(toml)).map_err(|error| {
+        let path = error.path().to_string();
+        let inner = error.into_inner();
+        // toml reports a byte span, turned into the 1-based line and column it starts at
+        let (line, column) = inner.span().map_or((0, 0), |span| {
+            let before = &toml[..span.start];
+            let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+            (
+                before.matches('\n').count() + 1,
+                before[line_start..].chars().count() + 1,
+            )
+        });
+        let message = inner.message().trim_end().to_string();
+        if path != "." {
+            ConfigError::Field {
+                path,
+                line,
+                column,
+                message,
+            }
+        } else {
+            ConfigError::Syntax {
+                line,
+                column,
+                message,
+            }
+        }
+    })
+}
+
+// serde_json appends " at line L column C" to its messages, which ConfigError reports on its own
+fn strip_position(error: &serde_json::Error) -> String {
+    let message = error.to_string();
+    let suffix = format!(" at line {} column {}", error.line(), error.column());
+    message
+        .strip_suffix(&suffix)
+        .unwrap_or(&message)
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ep_dto::{RampSchedule, RampStep, Target, Traffic, Variant, VariantRule};
+    use std::collections::HashMap;
+
+    fn color_document(base_mod: &str) -> String {
+        format!(
+            r##"{{
+  "schema_version": 1,
+  "experiments": [
+    {{
+      "name": "Color Experiment",
+      "context_expression": "AND(IN(SITEID, 0, 77), IN(CHANNELID, 1, 5, 6), EQ(F90D, \"TRUE\"))",
+      "hashing_constant": "0XF23AC",
+      "experiment_id": 65536,
+      "variant_rules": [
+        {{
+          "rule_id": 0,
+          "context_expression": "AND(IN(SITEID, 0), IN(CHANNELID, 1))",
+          "target": {{ "variant_mod_map": {{ "1024": "{zeros}{ones}" }} }}
+        }}
+      ],
+      "variants": [
+        {{
+          "name": "Red Variant",
+          "value": "#FF0000",
+          "variant_id": 1024,
+          "variant_display_id": "0aX0",
+          "variant_mod": "{ones}{zeros}",
+          "whitelisted_uids": ["1038812"]
+        }}
+      ],
+      "base_mod": "{base_mod}",
+      "randomization_unit_key": "LOOKUP_ID"
+    }}
+  ]
+}}"##,
+            ones = "1".repeat(50),
+            zeros = "0".repeat(50),
+            base_mod = base_mod
+        )
+    }
+
+    #[test]
+    fn load_color_experiment() {
+        let experiments = load_experiments_from_json(&color_document(&"1".repeat(100))).unwrap();
+        let expected = Experiment {
+            name: "Color Experiment".to_string(),
+            context_expression:
+                "AND(IN(SITEID, 0, 77), IN(CHANNELID, 1, 5, 6), EQ(F90D, \"TRUE\"))".to_string(),
+            hashing_constant: "0XF23AC".to_string(),
+            experiment_id: 65536,
+            experiment_flags: 0,
+            variant_rules: vec![VariantRule {
+                rule_id: 0,
+                context_expression: "AND(IN(SITEID, 0), IN(CHANNELID, 1))".to_string(),
+                target: Target {
+                    variant_mod_map: HashMap::from([(
+                        1024,
+                        Traffic::try_from("0".repeat(50) + &"1".repeat(50)).unwrap(),
+                    )]),
+                    ramp_map: HashMap::new(),
+                },
+            }],
+            variants: vec![Variant {
+                name: "Red Variant".to_string(),
+                value: "#FF0000".to_string(),
+                variant_id: 1024,
+                variant_display_id: "0aX0".to_string(),
+                variant_flags: 0,
+                variant_mod: Traffic::try_from("1".repeat(50) + &"0".repeat(50)).unwrap(),
+                whitelisted_uids: vec!["1038812".to_string()],
+                ramp: None,
+            }],
+            base_mod: Traffic::try_from("1".repeat(100)).unwrap(),
+            randomization_unit_key: "LOOKUP_ID".to_string(),
+            layer: None,
+            priority: 0,
+            bucket_count: 100,
+            start_time: None,
+            end_time: None,
+            paused: false,
+        };
+        assert_eq!(experiments, vec![expected]);
+        assert_eq!(
+            load_experiments_from_json(&experiments_to_json(&experiments)).unwrap(),
+            experiments
+        );
+    }
+
+    #[test]
+    fn invalid_field_reports_path() {
+        let error = load_experiments_from_json(&color_document("1112")).unwrap_err();
+        assert_eq!(
+            error,
+            ConfigError::Field {
+                path: "experiments[0].base_mod".to_string(),
+                line: 26,
+                column: 24,
+                message: "spectrum must only contain '0' and '1', found '2' at bucket 3"
+                    .to_string(),
+            }
+        );
+
+        let document = color_document(&"1".repeat(100)).replace("\"value\"", "\"valeu\"");
+        let error = load_experiments_from_json(&document).unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .starts_with("Invalid field experiments[0].variants[0].valeu at line 19"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn schema_version_is_checked() {
+        let document = color_document(&"1".repeat(100));
+        assert_eq!(
+            load_experiments_from_json(
+                &document.replace("\"schema_version\": 1", "\"schema_version\": 2")
+            ),
+            Err(ConfigError::UnsupportedSchemaVersion {
+                found: 2,
+                supported: 1
+            })
+        );
+        assert_eq!(
+            load_experiments_from_json(r#"{ "experiments": [] }"#),
+            Err(ConfigError::MissingSchemaVersion)
+        );
+        assert!(matches!(
+            load_experiments_from_json("{ \"schema_version\": 1, "),
+            Err(ConfigError::Syntax { line: 1, .. })
+        ));
+        assert!(matches!(
+            load_experiments_from_file("/nonexistent/experiments.json"),
+            Err(ConfigError::Io { .. })
+        ));
+    }
+
+    #[cfg(feature = "yaml")]
+    const COLOR_YAML: &str = r##"schema_version: 1
+experiments:
+  - name: Color Experiment
+    experiment_id: 65536
+    hashing_constant: 0XF23AC
+    randomization_unit_key: LOOKUP_ID
+    base_mod: { percent: 100 }
+    variant_rules:
+      - rule_id: 0
+        context_expression: AND(IN(SITEID, 0), IN(CHANNELID, 1))
+        target:
+          variant_mod_map:
+            1024: { buckets: 50-99 }
+    variants:
+      - name: Red Variant
+        value: "#FF0000"
+        variant_id: 1024
+        variant_display_id: 0aX0
+        variant_mod: { buckets: 0-49 }
+"##;
+
+    #[cfg(feature = "yaml")]
+    #[test]
+    fn load_yaml_with_bucket_notation() {
+        let experiments = load_experiments_from_yaml(COLOR_YAML).unwrap();
+        let expected = load_experiments_from_json(&color_document(&"1".repeat(100))).unwrap();
+        assert_eq!(experiments[0].base_mod, expected[0].base_mod);
+        assert_eq!(
+            experiments[0].variants[0].variant_mod,
+            expected[0].variants[0].variant_mod
+        );
+        assert_eq!(experiments[0].variant_rules, expected[0].variant_rules);
+
+        let yaml = experiments_to_yaml(&expected);
+        assert!(yaml.contains("buckets: 0-49"), "{}", yaml);
+        assert_eq!(load_experiments_from_yaml(&yaml).unwrap(), expected);
+
+        let mut canary = expected.clone();
+        canary[0].bucket_count = 1000;
+        canary[0].base_mod = Traffic::from_percent(0.1, 1000).unwrap();
+        let yaml = experiments_to_yaml(&canary);
+        assert!(yaml.contains("bucket_count: 1000"), "{}", yaml);
+        assert_eq!(load_experiments_from_yaml(&yaml).unwrap(), canary);
+
+        let mut ramped = expected.clone();
+        ramped[0].variant_rules[0].target.ramp_map = HashMap::from([(
+            1024,
+            RampSchedule {
+                steps: vec![RampStep {
+                    at: 1_700_000_000_000,
+                    percent: 2.5,
+                }],
+            },
+        )]);
+        let yaml = experiments_to_yaml(&ramped);
+        assert_eq!(load_experiments_from_yaml(&yaml).unwrap(), ramped);
+
+        let error = load_experiments_from_yaml(&COLOR_YAML.replace("0-49", "0-100")).unwrap_err();
+        assert_eq!(
+            error,
+            ConfigError::Field {
+                path: "experiments[0].variants[0].variant_mod".to_string(),
+                line: 19,
+                column: 22,
+                message: "bucket range 0-100 must hold buckets between 0 and 99".to_string(),
+            }
+        );
+        assert!(matches!(
+            load_experiments_from_yaml(&COLOR_YAML.replace("percent: 100", "share: 100")),
+            Err(ConfigError::Field { path, .. }) if path == "experiments[0].base_mod"
+        ));
+
+        let thousand_buckets = COLOR_YAML.replace("LOOKUP_ID", "LOOKUP_ID\n    bucket_count: 1000");
+        let experiments = load_experiments_from_yaml(&thousand_buckets).unwrap();
+        assert_eq!(experiments[0].base_mod.spectrum().len(), 1000);
+        assert_eq!(experiments[0].base_mod.to_buckets(), "0-999");
+        assert_eq!(
+            experiments[0].variant_rules[0].target.variant_mod_map[&1024].to_buckets(),
+            "50-99"
+        );
+        assert_eq!(
+            load_experiments_from_yaml(
+                &thousand_buckets.replace("percent: 100", "percent: 100, bucket_count: 1000")
+            )
+            .unwrap(),
+            experiments
+        );
+
+        let error = load_experiments_from_yaml(
+            &thousand_buckets.replace("buckets: 0-49", "buckets: 0-49, bucket_count: 100"),
+        )
+        .unwrap_err();
+        assert_eq!(
+            error,
+            ConfigError::TrafficBucketCount {
+                path: "experiments[0].variants[0].variant_mod".to_string(),
+                bucket_count: 100,
+                experiment_bucket_count: 1000,
+            }
+        );
+        assert_eq!(
+            error.to_string(),
+            "Invalid field experiments[0].variants[0].variant_mod: traffic over 100 buckets in an experiment of bucket_count 1000"
+        );
+
+        let oversized = COLOR_YAML.replace("LOOKUP_ID", "LOOKUP_ID\n    bucket_count: 4000000000");
+        assert_eq!(
+            load_experiments_from_yaml(&oversized),
+            Err(ConfigError::Field {
+                path: "experiments[0].bucket_count".to_string(),
+                line: 0,
+                column: 0,
+                message: "bucket_count must be between 1 and 1000000, found 4000000000".to_string(),
+            })
+        );
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn load_toml_with_bucket_notation() {
+        let toml = r##"schema_version = 1
+
+[[experiments]]
+name = "Color Experiment"
+experiment_id = 65536
+randomization_unit_key = "LOOKUP_ID"
+base_mod = { percent = 50 }
+
+[[experiments.variants]]
+name = "Red Variant"
+value = "#FF0000"
+variant_id = 1024
+variant_display_id = "0aX0"
+variant_mod = { buckets = "0-9, 40-49" }
+ramp = [{ at = 1700000000000, percent = 1 }, { at = 1700086400000, percent = 5 }]
+"##;
+        let experiments = load_experiments_from_toml(toml).unwrap();
+        assert_eq!(experiments[0].base_mod.to_buckets(), "0-49");
+        assert_eq!(
+            experiments[0].variants[0].variant_mod.to_buckets(),
+            "0-9, 40-49"
+        );
+        let ramp_schedule = experiments[0].variants[0].ramp.as_ref().unwrap();
+        assert_eq!(ramp_schedule.percent_at(1_700_086_399_999), 1.0);
+        assert_eq!(ramp_schedule.percent_at(1_700_086_400_000), 5.0);
+
+        let expected = load_experiments_from_json(&color_document(&"1".repeat(100))).unwrap();
+        let written = experiments_to_toml(&expected);
+        assert_eq!(load_experiments_from_toml(&written).unwrap(), expected);
+
+        let error = load_experiments_from_toml(&toml.replace("percent = 50", "percent = 50.5"))
+            .unwrap_err();
+        assert!(
+            matches!(&error, ConfigError::Field { path, line: 7, .. } if path == "experiments[0].base_mod"),
+            "{}",
+            error
+        );
+        assert!(matches!(
+            load_experiments_from_toml("schema_version = "),
+            Err(ConfigError::Syntax { line: 1, .. })
+        ));
+    }
+}
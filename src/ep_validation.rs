@@ -0,0 +1,866 @@
+use crate::context_expression::{Expression, ParseError};
+use crate::ep_dto::{Experiment, Traffic};
+use crate::hashing::{MAX_BUCKET_COUNT, experiment_salt};
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+
+// Internal inconsistency of an Experiment, found without evaluating any request.
+// rule_id is Some for the spectrums and expression of a VariantRule.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExperimentFinding {
+    // Two variant spectrums allocate the same buckets, only the first variant can ever be assigned them
+    OverlappingVariants {
+        rule_id: Option<i32>,
+        variant_ids: (i32, i32),
+        bucket_count: usize,
+    },
+    // A variant spectrum allocates buckets outside base_mod, they are never assigned
+    VariantOutsideBaseMod {
+        rule_id: Option<i32>,
+        variant_id: i32,
+        bucket_count: usize,
+    },
+    // A Target::variant_mod_map key which is not the variant_id of any variant
+    UnknownTargetVariant {
+        rule_id: i32,
+        variant_id: i32,
+    },
+    DuplicateDisplayId {
+        display_id: String,
+        variant_ids: Vec<i32>,
+    },
+    DuplicateRuleId {
+        rule_id: i32,
+    },
+    MalformedExpression {
+        rule_id: Option<i32>,
+        parse_error: ParseError,
+    },
+    // base_mod (variant_id None) or a variant spectrum does not hold one bucket per bucket_count
+    InvalidSpectrum {
+        rule_id: Option<i32>,
+        variant_id: Option<i32>,
+        message: String,
+    },
+    // bucket_count is 0 or above MAX_BUCKET_COUNT, no spectrum can be checked against it
+    InvalidBucketCount {
+        bucket_count: u32,
+    },
+    // A Variant::ramp or Target::ramp_map schedule which is out of order or never applies
+    InvalidRamp {
+        rule_id: Option<i32>,
+        variant_id: i32,
+        message: String,
+    },
+    // end_time is not after start_time, the experiment is never active
+    EmptySchedule {
+        start_time: u64,
+        end_time: u64,
+    },
+}
+
+impl ExperimentFinding {
+    pub fn rule_id(&self) -> Option<i32> {
+        match self {
+            ExperimentFinding::OverlappingVariants { rule_id, .. }
+            | ExperimentFinding::VariantOutsideBaseMod { rule_id, .. }
+            | ExperimentFinding::MalformedExpression { rule_id, .. }
+            | ExperimentFinding::InvalidSpectrum { rule_id, .. }
+            | ExperimentFinding::InvalidRamp { rule_id, .. } => *rule_id,
+            ExperimentFinding::UnknownTargetVariant { rule_id, .. }
+            | ExperimentFinding::DuplicateRuleId { rule_id } => Some(*rule_id),
+            ExperimentFinding::DuplicateDisplayId { .. }
+            | ExperimentFinding::InvalidBucketCount { .. }
+            | ExperimentFinding::EmptySchedule { .. } => None,
+        }
+    }
+}
+
+impl fmt::Display for ExperimentFinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(rule_id) = self.rule_id() {
+            write!(f, "rule_id {}: ", rule_id)?;
+        }
+        match self {
+            ExperimentFinding::OverlappingVariants {
+                variant_ids: (first_variant_id, second_variant_id),
+                bucket_count,
+                ..
+            } => write!(
+                f,
+                "Variants {} and {} overlap on {} buckets",
+                first_variant_id, second_variant_id, bucket_count
+            ),
+            ExperimentFinding::VariantOutsideBaseMod {
+                variant_id,
+                bucket_count,
+                ..
+            } => write!(
+                f,
+                "Variant {} allocates {} buckets outside base_mod",
+                variant_id, bucket_count
+            ),
+            ExperimentFinding::UnknownTargetVariant { variant_id, .. } => write!(
+                f,
+                "Target variant {} is not a variant of the experiment",
+                variant_id
+            ),
+            ExperimentFinding::DuplicateDisplayId {
+                display_id,
+                variant_ids,
+            } => write!(
+                f,
+                "Variant display id {} is shared by variants {}",
+                display_id,
+                variant_ids
+                    .iter()
+                    .map(i32::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            ExperimentFinding::DuplicateRuleId { .. } => write!(f, "Declared more than once"),
+            ExperimentFinding::MalformedExpression { parse_error, .. } => {
+                write!(f, "Malformed context expression: {}", parse_error)
+            }
+            ExperimentFinding::InvalidSpectrum {
+                variant_id: Some(variant_id),
+                message,
+                ..
+            } => write!(
+                f,
+                "Invalid spectrum for variant {}: {}",
+                variant_id, message
+            ),
+            ExperimentFinding::InvalidSpectrum {
+                variant_id: None,
+                message,
+                ..
+            } => write!(f, "Invalid spectrum for base_mod: {}", message),
+            ExperimentFinding::InvalidBucketCount { bucket_count } => write!(
+                f,
+                "bucket_count {} must be between 1 and {}",
+                bucket_count, MAX_BUCKET_COUNT
+            ),
+            ExperimentFinding::InvalidRamp {
+                variant_id,
+                message,
+                ..
+            } => write!(f, "Invalid ramp for variant {}: {}", variant_id, message),
+            ExperimentFinding::EmptySchedule {
+                start_time,
+                end_time,
+            } => write!(
+                f,
+                "Never active: end_time {} is not after start_time {}",
+                end_time, start_time
+            ),
+        }
+    }
+}
+
+// Every inconsistency of the experiment, in declaration order
+pub fn validate_experiment(experiment: &Experiment) -> Vec<ExperimentFinding> {
+    let mut findings = vec![];
+    findings.extend(malformed_expression(None, &experiment.context_expression));
+    if let (Some(start_time), Some(end_time)) = (experiment.start_time, experiment.end_time)
+        && end_time <= start_time
+    {
+        findings.push(ExperimentFinding::EmptySchedule {
+            start_time,
+            end_time,
+        });
+    }
+
+    let mut variant_ids_by_display_id = BTreeMap::<&str, Vec<i32>>::new();
+    for variant in &experiment.variants {
+        variant_ids_by_display_id
+            .entry(&variant.variant_display_id)
+            .or_default()
+            .push(variant.variant_id);
+    }
+    findings.extend(
+        variant_ids_by_display_id
+            .into_iter()
+            .filter(|(_, variant_ids)| variant_ids.len() > 1)
+            .map(
+                |(display_id, variant_ids)| ExperimentFinding::DuplicateDisplayId {
+                    display_id: display_id.to_string(),
+                    variant_ids,
+                },
+            ),
+    );
+
+    // Spectrums are only checked against a usable bucket_count
+    let bucket_count_valid = (1..=MAX_BUCKET_COUNT).contains(&experiment.bucket_count);
+    if !bucket_count_valid {
+        findings.push(ExperimentFinding::InvalidBucketCount {
+            bucket_count: experiment.bucket_count,
+        });
+    }
+    let base_mod_valid = match experiment.base_mod.validate(experiment.bucket_count) {
+        Ok(()) => true,
+        Err(_) if !bucket_count_valid => false,
+        Err(message) => {
+            findings.push(ExperimentFinding::InvalidSpectrum {
+                rule_id: None,
+                variant_id: None,
+                message,
+            });
+            false
+        }
+    };
+    let variant_mods: Vec<_> = experiment
+        .variants
+        .iter()
+        .map(|variant| (variant.variant_id, &variant.variant_mod))
+        .collect();
+    if bucket_count_valid {
+        findings.extend(spectrum_findings(
+            experiment,
+            None,
+            &variant_mods,
+            base_mod_valid,
+        ));
+    }
+    findings.extend(experiment.variants.iter().filter_map(|variant| {
+        let message = variant.ramp.as_ref()?.validate().err()?;
+        Some(ExperimentFinding::InvalidRamp {
+            rule_id: None,
+            variant_id: variant.variant_id,
+            message,
+        })
+    }));
+
+    let mut rule_ids = HashSet::new();
+    for variant_rule in &experiment.variant_rules {
+        if !rule_ids.insert(variant_rule.rule_id) {
+            findings.push(ExperimentFinding::DuplicateRuleId {
+                rule_id: variant_rule.rule_id,
+            });
+        }
+        findings.extend(malformed_expression(
+            Some(variant_rule.rule_id),
+            &variant_rule.context_expression,
+        ));
+        let mut unknown_variant_ids: Vec<_> =
+            variant_rule
+                .target
+                .variant_mod_map
+                .keys()
+                .chain(variant_rule.target.ramp_map.keys().filter(|variant_id| {
+                    !variant_rule.target.variant_mod_map.contains_key(variant_id)
+                }))
+                .filter(|variant_id| {
+                    !experiment
+                        .variants
+                        .iter()
+                        .any(|variant| variant.variant_id == **variant_id)
+                })
+                .copied()
+                .collect();
+        unknown_variant_ids.sort();
+        findings.extend(unknown_variant_ids.into_iter().map(|variant_id| {
+            ExperimentFinding::UnknownTargetVariant {
+                rule_id: variant_rule.rule_id,
+                variant_id,
+            }
+        }));
+        // Known variants in declaration order, as the VariantRuleMapper assigns them
+        let target_mods: Vec<_> = experiment
+            .variants
+            .iter()
+            .filter_map(|variant| {
+                let traffic = variant_rule
+                    .target
+                    .variant_mod_map
+                    .get(&variant.variant_id)?;
+                Some((variant.variant_id, traffic))
+            })
+            .collect();
+        if bucket_count_valid {
+            findings.extend(spectrum_findings(
+                experiment,
+                Some(variant_rule.rule_id),
+                &target_mods,
+                base_mod_valid,
+            ));
+        }
+        findings.extend(experiment.variants.iter().filter_map(|variant| {
+            let ramp_schedule = variant_rule.target.ramp_map.get(&variant.variant_id)?;
+            let message = if variant_rule
+                .target
+                .variant_mod_map
+                .contains_key(&variant.variant_id)
+            {
+                ramp_schedule.validate().err()?
+            } else {
+                "no spectrum in variant_mod_map to ramp".to_string()
+            };
+            Some(ExperimentFinding::InvalidRamp {
+                rule_id: Some(variant_rule.rule_id),
+                variant_id: variant.variant_id,
+                message,
+            })
+        }));
+    }
+    findings
+}
+
+fn malformed_expression(
+    rule_id: Option<i32>,
+    context_expression: &str,
+) -> Option<ExperimentFinding> {
+    if context_expression.trim().is_empty() {
+        return None;
+    }
+    let parse_error = Expression::parse(context_expression).err()?;
+    Some(ExperimentFinding::MalformedExpression {
+        rule_id,
+        parse_error,
+    })
+}
+
+// Invalid, overlapping and out of base_mod variant spectrums; overlaps and base_mod are only
+// checked between spectrums of the right length
+fn spectrum_findings(
+    experiment: &Experiment,
+    rule_id: Option<i32>,
+    variant_mods: &[(i32, &Traffic)],
+    base_mod_valid: bool,
+) -> Vec<ExperimentFinding> {
+    let mut findings = vec![];
+    let mut valid_variant_mods = vec![];
+    for (variant_id, traffic) in variant_mods {
+        match traffic.validate(experiment.bucket_count) {
+            Ok(()) => valid_variant_mods.push((*variant_id, *traffic)),
+            Err(message) => findings.push(ExperimentFinding::InvalidSpectrum {
+                rule_id,
+                variant_id: Some(*variant_id),
+                message,
+            }),
+        }
+    }
+    for (index, (variant_id, traffic)) in valid_variant_mods.iter().enumerate() {
+        for (other_variant_id, other_traffic) in &valid_variant_mods[index + 1..] {
+            let bucket_count = traffic
+                .spectrum()
+                .intersection(other_traffic.spectrum())
+                .count_ones();
+            if bucket_count > 0 {
+                findings.push(ExperimentFinding::OverlappingVariants {
+                    rule_id,
+                    variant_ids: (*variant_id, *other_variant_id),
+                    bucket_count,
+                });
+            }
+        }
+        if base_mod_valid {
+            let bucket_count = traffic
+                .spectrum()
+                .intersection(&experiment.base_mod.spectrum().complement())
+                .count_ones();
+            if bucket_count > 0 {
+                findings.push(ExperimentFinding::VariantOutsideBaseMod {
+                    rule_id,
+                    variant_id: *variant_id,
+                    bucket_count,
+                });
+            }
+        }
+    }
+    findings
+}
+
+// Problem only visible across a whole experiment set
+#[derive(Debug, Clone, PartialEq)]
+pub enum LintFinding {
+    // Results, opt-ins and overrides can no longer tell the experiments apart
+    DuplicateExperimentId {
+        experiment_id: i32,
+    },
+    // An opt-in of the display id is ambiguous
+    DuplicateDisplayId {
+        display_id: String,
+        experiment_ids: Vec<i32>,
+    },
+    // Experiments outside a layer hashing with the same salt assign correlated buckets
+    SharedHashingConstant {
+        hashing_constant: String,
+        experiment_ids: Vec<i32>,
+    },
+    // Experiments of a layer hash with the layer salt and split its buckets, which only holds when
+    // they agree on bucket_count and randomization_unit_key
+    InconsistentLayer {
+        layer: String,
+        experiment_ids: Vec<i32>,
+    },
+    // Experiments outside a common layer targeting the same users on the same randomization unit,
+    // hashed with the same salt into intersecting base_mod buckets
+    OverlappingTargeting {
+        experiment_ids: (i32, i32),
+        randomization_unit_key: String,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LintSeverity {
+    Warning, // the set qualifies but likely not as intended
+    Error,   // the set must not be shipped
+}
+
+impl LintFinding {
+    pub fn severity(&self) -> LintSeverity {
+        match self {
+            LintFinding::DuplicateExperimentId { .. }
+            | LintFinding::DuplicateDisplayId { .. }
+            | LintFinding::InconsistentLayer { .. } => LintSeverity::Error,
+            LintFinding::SharedHashingConstant { .. }
+            | LintFinding::OverlappingTargeting { .. } => LintSeverity::Warning,
+        }
+    }
+}
+
+impl fmt::Display for LintFinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let join = |experiment_ids: &[i32]| {
+            experiment_ids
+                .iter()
+                .map(i32::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        match self {
+            LintFinding::DuplicateExperimentId { experiment_id } => {
+                write!(
+                    f,
+                    "experiment_id {} is declared more than once",
+                    experiment_id
+                )
+            }
+            LintFinding::DuplicateDisplayId {
+                display_id,
+                experiment_ids,
+            } => write!(
+                f,
+                "Variant display id {} is used by experiments {}",
+                display_id,
+                join(experiment_ids)
+            ),
+            LintFinding::SharedHashingConstant {
+                hashing_constant,
+                experiment_ids,
+            } => write!(
+                f,
+                "Experiments {} share the hashing_constant {}",
+                join(experiment_ids),
+                hashing_constant
+            ),
+            LintFinding::InconsistentLayer {
+                layer,
+                experiment_ids,
+            } => write!(
+                f,
+                "Experiments {} of layer {} differ in bucket_count or randomization_unit_key",
+                join(experiment_ids),
+                layer
+            ),
+            LintFinding::OverlappingTargeting {
+                experiment_ids: (first_experiment_id, second_experiment_id),
+                randomization_unit_key,
+            } => write!(
+                f,
+                "Experiments {} and {} target the same users on {}",
+                first_experiment_id, second_experiment_id, randomization_unit_key
+            ),
+        }
+    }
+}
+
+// Cross-experiment problems of the set, errors first; validate_experiment covers each experiment
+pub fn lint_experiments(experiments: &[Experiment]) -> Vec<LintFinding> {
+    let mut findings = vec![];
+
+    let mut experiment_ids = BTreeMap::<i32, usize>::new();
+    for experiment in experiments {
+        *experiment_ids.entry(experiment.experiment_id).or_default() += 1;
+    }
+    findings.extend(
+        experiment_ids
+            .into_iter()
+            .filter(|(_, count)| *count > 1)
+            .map(|(experiment_id, _)| LintFinding::DuplicateExperimentId { experiment_id }),
+    );
+
+    let mut experiment_ids_by_display_id = BTreeMap::<&str, Vec<i32>>::new();
+    for experiment in experiments {
+        for variant in &experiment.variants {
+            let experiment_ids = experiment_ids_by_display_id
+                .entry(&variant.variant_display_id)
+                .or_default();
+            if !experiment_ids.contains(&experiment.experiment_id) {
+                experiment_ids.push(experiment.experiment_id);
+            }
+        }
+    }
+    findings.extend(
+        experiment_ids_by_display_id
+            .into_iter()
+            .filter(|(_, experiment_ids)| experiment_ids.len() > 1)
+            .map(
+                |(display_id, experiment_ids)| LintFinding::DuplicateDisplayId {
+                    display_id: display_id.to_string(),
+                    experiment_ids,
+                },
+            ),
+    );
+
+    // Layered experiments share their layer salt on purpose and partition its buckets
+    let mut experiment_ids_by_salt = BTreeMap::<String, Vec<i32>>::new();
+    for experiment in experiments
+        .iter()
+        .filter(|experiment| experiment.layer.is_none())
+    {
+        let experiment_ids = experiment_ids_by_salt
+            .entry(experiment_salt(experiment))
+            .or_default();
+        if !experiment_ids.contains(&experiment.experiment_id) {
+            experiment_ids.push(experiment.experiment_id);
+        }
+    }
+    findings.extend(
+        experiment_ids_by_salt
+            .into_iter()
+            .filter(|(_, experiment_ids)| experiment_ids.len() > 1)
+            .map(
+                |(hashing_constant, experiment_ids)| LintFinding::SharedHashingConstant {
+                    hashing_constant,
+                    experiment_ids,
+                },
+            ),
+    );
+
+    let mut experiments_by_layer = BTreeMap::<&str, Vec<&Experiment>>::new();
+    for experiment in experiments {
+        if let Some(layer) = &experiment.layer {
+            experiments_by_layer
+                .entry(layer)
+                .or_default()
+                .push(experiment);
+        }
+    }
+    findings.extend(
+        experiments_by_layer
+            .into_iter()
+            .filter(|(_, layered)| {
+                layered.iter().any(|experiment| {
+                    experiment.bucket_count != layered[0].bucket_count
+                        || experiment.randomization_unit_key != layered[0].randomization_unit_key
+                })
+            })
+            .map(|(layer, layered)| LintFinding::InconsistentLayer {
+                layer: layer.to_string(),
+                experiment_ids: layered
+                    .iter()
+                    .map(|experiment| experiment.experiment_id)
+                    .collect(),
+            }),
+    );
+
+    // Targeting is compared on the normalized expressions, so formatting and operand order do not
+    // matter; unparsable expressions are left to validate_experiment
+    let targeting: Vec<_> = experiments
+        .iter()
+        .filter_map(|experiment| {
+            let expression = experiment.context_expression.trim();
+            let expression = if expression.is_empty() {
+                None
+            } else {
+                Some(Expression::parse(expression).ok()?.normalized())
+            };
+            Some((experiment, expression))
+        })
+        .collect();
+    // Users only collide when both experiments put them in a bucket of their base_mod, which
+    // takes the same salt and base_mods of the same bucket_count sharing a bucket
+    let share_buckets = |experiment: &Experiment, other_experiment: &Experiment| {
+        experiment_salt(experiment) == experiment_salt(other_experiment)
+            && experiment.bucket_count == other_experiment.bucket_count
+            && experiment
+                .base_mod
+                .validate(experiment.bucket_count)
+                .is_ok()
+            && other_experiment
+                .base_mod
+                .validate(other_experiment.bucket_count)
+                .is_ok()
+            && experiment
+                .base_mod
+                .spectrum()
+                .intersection(other_experiment.base_mod.spectrum())
+                .count_ones()
+                > 0
+    };
+    for (index, (experiment, expression)) in targeting.iter().enumerate() {
+        for (other_experiment, other_expression) in &targeting[index + 1..] {
+            let same_layer =
+                experiment.layer.is_some() && experiment.layer == other_experiment.layer;
+            if !same_layer
+                && experiment.experiment_id != other_experiment.experiment_id
+                && experiment.randomization_unit_key == other_experiment.randomization_unit_key
+                && expression == other_expression
+                && share_buckets(experiment, other_experiment)
+            {
+                findings.push(LintFinding::OverlappingTargeting {
+                    experiment_ids: (experiment.experiment_id, other_experiment.experiment_id),
+                    randomization_unit_key: experiment.randomization_unit_key.clone(),
+                });
+            }
+        }
+    }
+    findings.sort_by_key(|finding| std::cmp::Reverse(finding.severity()));
+    findings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ep_dto::{RampSchedule, RampStep, Target, Variant, VariantRule};
+    use std::collections::HashMap;
+
+    fn traffic(spectrum: String) -> Traffic {
+        Traffic::try_from(spectrum).unwrap()
+    }
+
+    fn variant(variant_id: i32, variant_display_id: &str, spectrum: String) -> Variant {
+        Variant {
+            name: format!("Variant {}", variant_id),
+            value: variant_id.to_string(),
+            variant_id,
+            variant_display_id: variant_display_id.to_string(),
+            variant_flags: 0,
+            variant_mod: traffic(spectrum),
+            whitelisted_uids: vec![],
+            ramp: None,
+        }
+    }
+
+    fn split_experiment() -> Experiment {
+        Experiment {
+            name: "Split Experiment".to_string(),
+            context_expression: "IN(SITEID, 0, 77)".to_string(),
+            hashing_constant: "0XF23AC".to_string(),
+            experiment_id: 1,
+            experiment_flags: 0,
+            variant_rules: vec![VariantRule {
+                rule_id: 0,
+                context_expression: "EQ(SITEID, 0)".to_string(),
+                target: Target {
+                    variant_mod_map: HashMap::from([
+                        (10, traffic("0".repeat(50) + &"1".repeat(50))),
+                        (11, traffic("1".repeat(50) + &"0".repeat(50))),
+                    ]),
+                    ramp_map: HashMap::new(),
+                },
+            }],
+            variants: vec![
+                variant(10, "0aX0", "1".repeat(50) + &"0".repeat(50)),
+                variant(11, "0aX1", "0".repeat(50) + &"1".repeat(50)),
+            ],
+            base_mod: traffic("1".repeat(100)),
+            randomization_unit_key: "LOOKUP_ID".to_string(),
+            layer: None,
+            priority: 0,
+            bucket_count: 100,
+            start_time: None,
+            end_time: None,
+            paused: false,
+        }
+    }
+
+    #[test]
+    fn consistent_experiment_has_no_findings() {
+        assert_eq!(validate_experiment(&split_experiment()), vec![]);
+    }
+
+    #[test]
+    fn every_finding_is_reported() {
+        let mut experiment = split_experiment();
+        experiment.context_expression = "IN(SITEID".to_string();
+        experiment.start_time = Some(1_700_000_000_000);
+        experiment.end_time = Some(1_700_000_000_000);
+        experiment.base_mod = traffic("1".repeat(90) + &"0".repeat(10));
+        experiment.variants[1].variant_display_id = "0aX0".to_string();
+        experiment.variants[1].variant_mod = traffic("0".repeat(40) + &"1".repeat(60));
+        experiment.variants[1].ramp = Some(RampSchedule {
+            steps: vec![RampStep {
+                at: 1_700_000_000_000,
+                percent: 150.0,
+            }],
+        });
+        let mut second_rule = experiment.variant_rules[0].clone();
+        second_rule.context_expression = "FOO(SITEID)".to_string();
+        second_rule.target.variant_mod_map = HashMap::from([
+            (10, traffic("1".repeat(99))),
+            (12, traffic("1".repeat(100))),
+        ]);
+        let ramp_schedule = RampSchedule {
+            steps: vec![RampStep {
+                at: 1_700_000_000_000,
+                percent: 5.0,
+            }],
+        };
+        second_rule.target.ramp_map =
+            HashMap::from([(11, ramp_schedule.clone()), (13, ramp_schedule)]);
+        experiment.variant_rules.push(second_rule);
+
+        let findings = validate_experiment(&experiment);
+        let messages: Vec<_> = findings.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "Malformed context expression: expected ',' after context key of IN, found end of input at position 9",
+                "Never active: end_time 1700000000000 is not after start_time 1700000000000",
+                "Variant display id 0aX0 is shared by variants 10, 11",
+                "Variants 10 and 11 overlap on 10 buckets",
+                "Variant 11 allocates 10 buckets outside base_mod",
+                "Invalid ramp for variant 11: percent 150 must be between 0 and 100",
+                "rule_id 0: Variant 10 allocates 10 buckets outside base_mod",
+                "rule_id 0: Declared more than once",
+                "rule_id 0: Malformed context expression: unknown operator FOO at position 0",
+                "rule_id 0: Target variant 12 is not a variant of the experiment",
+                "rule_id 0: Target variant 13 is not a variant of the experiment",
+                "rule_id 0: Invalid spectrum for variant 10: spectrum must be 100 characters, found 99",
+                "rule_id 0: Invalid ramp for variant 11: no spectrum in variant_mod_map to ramp",
+            ]
+        );
+        assert_eq!(
+            findings[3],
+            ExperimentFinding::OverlappingVariants {
+                rule_id: None,
+                variant_ids: (10, 11),
+                bucket_count: 10,
+            }
+        );
+    }
+
+    #[test]
+    fn oversized_bucket_count_is_reported_once() {
+        let mut experiment = split_experiment();
+        experiment.bucket_count = 4_000_000_000;
+        let findings = validate_experiment(&experiment);
+        assert_eq!(
+            findings,
+            vec![ExperimentFinding::InvalidBucketCount {
+                bucket_count: 4_000_000_000
+            }]
+        );
+        assert_eq!(
+            findings[0].to_string(),
+            "bucket_count 4000000000 must be between 1 and 1000000"
+        );
+    }
+
+    #[test]
+    fn lint_reports_cross_experiment_findings() {
+        let mut second_experiment = split_experiment();
+        second_experiment.experiment_id = 2;
+        second_experiment.context_expression = "IN(SITEID,0,77)".to_string();
+        second_experiment.variants[0].variant_display_id = "0bX0".to_string();
+        second_experiment.variants[1].variant_display_id = "0bX1".to_string();
+        let mut third_experiment = second_experiment.clone();
+        third_experiment.hashing_constant = "0XBEEF".to_string();
+        third_experiment.randomization_unit_key = "GUID".to_string();
+        let mut layered_experiments = [split_experiment(), split_experiment()];
+        for (index, experiment) in layered_experiments.iter_mut().enumerate() {
+            experiment.experiment_id = 4 + index as i32;
+            experiment.context_expression = "EQ(SITEID, 0)".to_string();
+            experiment.layer = Some("checkout".to_string());
+            experiment.variants[0].variant_display_id = format!("0cX{}", index);
+            experiment.variants[1].variant_display_id = format!("0cX{}", index + 1);
+        }
+        let mut experiments = vec![
+            split_experiment(),
+            second_experiment,
+            third_experiment.clone(),
+            third_experiment,
+        ];
+        experiments.extend(layered_experiments);
+
+        let findings = lint_experiments(&experiments);
+        let messages: Vec<_> = findings
+            .iter()
+            .map(|finding| format!("{:?}: {}", finding.severity(), finding))
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                "Error: experiment_id 2 is declared more than once",
+                "Error: Variant display id 0cX1 is used by experiments 4, 5",
+                "Warning: Experiments 1, 2 share the hashing_constant 0XF23AC",
+                "Warning: Experiments 1 and 2 target the same users on LOOKUP_ID",
+            ]
+        );
+        assert_eq!(lint_experiments(&experiments[..1]), vec![]);
+    }
+
+    #[test]
+    fn lint_reports_overlapping_targeting_on_shared_buckets() {
+        let mut second_experiment = split_experiment();
+        second_experiment.experiment_id = 2;
+        second_experiment.context_expression = "IN(SITEID, 77, 0)".to_string();
+        second_experiment.variants[0].variant_display_id = "0bX0".to_string();
+        second_experiment.variants[1].variant_display_id = "0bX1".to_string();
+        let overlapping = |experiments: &[Experiment]| {
+            lint_experiments(experiments)
+                .into_iter()
+                .filter(|finding| matches!(finding, LintFinding::OverlappingTargeting { .. }))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            overlapping(&[split_experiment(), second_experiment.clone()]),
+            vec![LintFinding::OverlappingTargeting {
+                experiment_ids: (1, 2),
+                randomization_unit_key: "LOOKUP_ID".to_string(),
+            }]
+        );
+
+        let mut first_experiment = split_experiment();
+        first_experiment.base_mod = traffic("1".repeat(50) + &"0".repeat(50));
+        second_experiment.base_mod = traffic("0".repeat(50) + &"1".repeat(50));
+        assert_eq!(
+            overlapping(&[first_experiment.clone(), second_experiment.clone()]),
+            vec![]
+        );
+
+        second_experiment.base_mod = traffic("1".repeat(100));
+        second_experiment.hashing_constant = "0XBEEF".to_string();
+        assert_eq!(overlapping(&[first_experiment, second_experiment]), vec![]);
+    }
+
+    #[test]
+    fn lint_reports_inconsistent_layers() {
+        let mut experiments: Vec<_> = (0..4).map(|_| split_experiment()).collect();
+        for (index, experiment) in experiments.iter_mut().enumerate() {
+            experiment.experiment_id = index as i32 + 1;
+            experiment.layer = Some(if index < 2 { "checkout" } else { "search" }.to_string());
+            experiment.context_expression = format!("EQ(SITEID, {})", index);
+            experiment.variants[0].variant_display_id = format!("0aX{}", 2 * index);
+            experiment.variants[1].variant_display_id = format!("0aX{}", 2 * index + 1);
+        }
+        assert_eq!(lint_experiments(&experiments), vec![]);
+
+        experiments[1].bucket_count = 1000;
+        experiments[3].randomization_unit_key = "GUID".to_string();
+        let messages: Vec<_> = lint_experiments(&experiments)
+            .iter()
+            .map(|finding| format!("{:?}: {}", finding.severity(), finding))
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                "Error: Experiments 1, 2 of layer checkout differ in bucket_count or randomization_unit_key",
+                "Error: Experiments 3, 4 of layer search differ in bucket_count or randomization_unit_key",
+            ]
+        );
+    }
+}
@@ -0,0 +1,258 @@
+use crate::ep_dto::{BASE_HASHING_CONSTANT, Experiment, Holdout, Traffic, Variant};
+
+// Number of buckets of an experiment which does not declare its bucket_count
+#[cfg_attr(not(feature = "serde"), allow(dead_code))]
+pub const DEFAULT_BUCKET_COUNT: u32 = 100;
+
+// Largest bucket_count of an experiment or holdout, a 0.0001% traffic granularity. Spectrums are
+// allocated whole, so a larger count makes loading and evaluating slow and memory hungry.
+pub const MAX_BUCKET_COUNT: u32 = 1_000_000;
+
+// Bucketing of a randomization unit, reproducible in any language:
+//   salt   = BASE_HASHING_CONSTANT ("EXPT") + Experiment::layer for a layered experiment, otherwise
+//            Experiment::hashing_constant, or BASE_HASHING_CONSTANT when it is empty
+//   hash   = MurmurHash3_x86_32(UTF-8 bytes of salt + unit value, seed 0)
+//   bucket = hash % Experiment::bucket_count (100 unless declared)
+// The bucket indexes the '0'/'1' characters of base_mod and every variant_mod (0 is the leftmost).
+// Holdouts bucket the same way over Holdout::traffic, salted with Holdout::hashing_constant, or
+// BASE_HASHING_CONSTANT + "HOLDOUT" + Holdout::holdout_id when it is empty.
+pub fn bucket_of(salt: &str, unit_value: &str, bucket_count: u32) -> u32 {
+    let mut key = String::with_capacity(salt.len() + unit_value.len());
+    key.push_str(salt);
+    key.push_str(unit_value);
+    murmur3_32(key.as_bytes(), 0) % bucket_count
+}
+
+pub fn experiment_salt(experiment: &Experiment) -> String {
+    match &experiment.layer {
+        Some(layer) => format!("{}{}", BASE_HASHING_CONSTANT, layer),
+        None if experiment.hashing_constant.is_empty() => BASE_HASHING_CONSTANT.to_string(),
+        None => experiment.hashing_constant.clone(),
+    }
+}
+
+pub fn holdout_salt(holdout: &Holdout) -> String {
+    if holdout.hashing_constant.is_empty() {
+        format!("{}HOLDOUT{}", BASE_HASHING_CONSTANT, holdout.holdout_id)
+    } else {
+        holdout.hashing_constant.clone()
+    }
+}
+
+// Outcome of placing a randomization unit into an experiment's traffic
+#[derive(Debug, PartialEq)]
+pub enum BucketAssignment<'a> {
+    OutsideBaseMod { bucket: u32 },
+    Unallocated { bucket: u32 },
+    Assigned { bucket: u32, variant: &'a Variant },
+}
+
+// Assign a unit to the first variant whose traffic holds its bucket, provided base_mod holds it too.
+// variant_mod_of supplies the traffic of each variant, None leaves the variant out.
+pub fn assign_variant_with<'a, F>(
+    experiment: &'a Experiment,
+    unit_value: &str,
+    variant_mod_of: F,
+) -> BucketAssignment<'a>
+where
+    F: Fn(&'a Variant) -> Option<&'a Traffic>,
+{
+    let bucket = bucket_of(
+        &experiment_salt(experiment),
+        unit_value,
+        experiment.bucket_count,
+    );
+    if !experiment.base_mod.contains_bucket(bucket) {
+        return BucketAssignment::OutsideBaseMod { bucket };
+    }
+    experiment
+        .variants
+        .iter()
+        .find(|variant| {
+            variant_mod_of(variant).is_some_and(|traffic| traffic.contains_bucket(bucket))
+        })
+        .map_or(BucketAssignment::Unallocated { bucket }, |variant| {
+            BucketAssignment::Assigned { bucket, variant }
+        })
+}
+
+// MurmurHash3 x86 32-bit, as published by Austin Appleby
+pub fn murmur3_32(data: &[u8], seed: u32) -> u32 {
+    const C1: u32 = 0xcc9e_2d51;
+    const C2: u32 = 0x1b87_3593;
+
+    let mut hash = seed;
+    let mut chunks = data.chunks_exact(4);
+    for chunk in &mut chunks {
+        let mut k = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        k = k.wrapping_mul(C1).rotate_left(15).wrapping_mul(C2);
+        hash ^= k;
+        hash = hash
+            .rotate_left(13)
+            .wrapping_mul(5)
+            .wrapping_add(0xe654_6b64);
+    }
+
+    let tail = chunks.remainder();
+    if !tail.is_empty() {
+        let mut k = 0u32;
+        for (index, byte) in tail.iter().enumerate() {
+            k |= (*byte as u32) << (8 * index);
+        }
+        k = k.wrapping_mul(C1).rotate_left(15).wrapping_mul(C2);
+        hash ^= k;
+    }
+
+    hash ^= data.len() as u32;
+    hash ^= hash >> 16;
+    hash = hash.wrapping_mul(0x85eb_ca6b);
+    hash ^= hash >> 13;
+    hash = hash.wrapping_mul(0xc2b2_ae35);
+    hash ^= hash >> 16;
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spectrum::Spectrum;
+
+    fn assign_variant<'a>(experiment: &'a Experiment, unit_value: &str) -> BucketAssignment<'a> {
+        assign_variant_with(experiment, unit_value, |variant| Some(&variant.variant_mod))
+    }
+
+    fn traffic(buckets: std::ops::Range<usize>) -> Traffic {
+        let mut spectrum = Spectrum::empty(DEFAULT_BUCKET_COUNT as usize);
+        buckets.for_each(|bucket| spectrum.insert(bucket));
+        Traffic::from(spectrum)
+    }
+
+    fn variant(variant_id: i32, buckets: std::ops::Range<usize>) -> Variant {
+        Variant {
+            name: format!("Variant {}", variant_id),
+            value: variant_id.to_string(),
+            variant_id,
+            variant_display_id: format!("0aX{}", variant_id),
+            variant_flags: 0,
+            variant_mod: traffic(buckets),
+            whitelisted_uids: vec![],
+            ramp: None,
+        }
+    }
+
+    fn experiment(base_mod: std::ops::Range<usize>) -> Experiment {
+        Experiment {
+            name: "Split Experiment".to_string(),
+            context_expression: "".to_string(),
+            hashing_constant: "0XF23AC".to_string(),
+            experiment_id: 1,
+            experiment_flags: 0,
+            variant_rules: vec![],
+            variants: vec![variant(1, 0..50), variant(2, 50..100)],
+            base_mod: traffic(base_mod),
+            randomization_unit_key: "LOOKUP_ID".to_string(),
+            layer: None,
+            priority: 0,
+            bucket_count: 100,
+            start_time: None,
+            end_time: None,
+            paused: false,
+        }
+    }
+
+    #[test]
+    fn murmur3_reference_vectors() {
+        assert_eq!(murmur3_32(b"", 0), 0);
+        assert_eq!(murmur3_32(b"", 1), 0x514e_28b7);
+        assert_eq!(murmur3_32(b"hello", 0), 0x248b_fa47);
+        assert_eq!(
+            murmur3_32(b"The quick brown fox jumps over the lazy dog", 0),
+            0x2e4f_f723
+        );
+    }
+
+    #[test]
+    fn bucket_is_stable() {
+        let bucket = bucket_of("0XF23AC", "search_88ax9i5", DEFAULT_BUCKET_COUNT);
+        assert_eq!(bucket, murmur3_32(b"0XF23ACsearch_88ax9i5", 0) % 100);
+        assert_eq!(
+            bucket,
+            bucket_of("0XF23AC", "search_88ax9i5", DEFAULT_BUCKET_COUNT)
+        );
+        assert_eq!(
+            bucket_of("", "1015529", DEFAULT_BUCKET_COUNT),
+            murmur3_32(b"1015529", 0) % 100
+        );
+    }
+
+    #[test]
+    fn buckets_are_spread() {
+        let mut counts = [0u32; DEFAULT_BUCKET_COUNT as usize];
+        for unit in 0..10_000 {
+            counts[bucket_of("EXPT", &unit.to_string(), DEFAULT_BUCKET_COUNT) as usize] += 1;
+        }
+        assert!(counts.iter().all(|count| (50..=150).contains(count)));
+    }
+
+    #[test]
+    fn assign_variant_follows_spectrums() {
+        let experiment = experiment(0..100);
+        for unit in 0..200 {
+            let unit_value = format!("user{}", unit);
+            let assignment = assign_variant(&experiment, &unit_value);
+            let bucket = bucket_of("0XF23AC", &unit_value, DEFAULT_BUCKET_COUNT);
+            let expected_variant_id = if bucket < 50 { 1 } else { 2 };
+            match assignment {
+                BucketAssignment::Assigned { variant, .. } => {
+                    assert_eq!(variant.variant_id, expected_variant_id)
+                }
+                other => panic!("unexpected {:?}", other),
+            }
+            assert_eq!(assign_variant(&experiment, &unit_value), assignment);
+        }
+    }
+
+    #[test]
+    fn assign_variant_outside_traffic() {
+        let mut experiment = experiment(0..0);
+        let assignment = assign_variant(&experiment, "user1");
+        assert_eq!(
+            assignment,
+            BucketAssignment::OutsideBaseMod {
+                bucket: bucket_of("0XF23AC", "user1", DEFAULT_BUCKET_COUNT)
+            }
+        );
+
+        experiment.base_mod = traffic(0..100);
+        experiment.hashing_constant = "".to_string();
+        let assignment = assign_variant_with(&experiment, "user1", |_| None);
+        assert_eq!(
+            assignment,
+            BucketAssignment::Unallocated {
+                bucket: bucket_of("EXPT", "user1", DEFAULT_BUCKET_COUNT)
+            }
+        );
+    }
+
+    #[test]
+    fn layered_experiments_share_buckets() {
+        let mut first_experiment = experiment(0..50);
+        first_experiment.layer = Some("checkout".to_string());
+        let mut second_experiment = experiment(50..100);
+        second_experiment.hashing_constant = "0XBEEF".to_string();
+        second_experiment.layer = Some("checkout".to_string());
+        assert_eq!(experiment_salt(&first_experiment), "EXPTcheckout");
+        for unit in 0..200 {
+            let unit_value = format!("user{}", unit);
+            let in_first = !matches!(
+                assign_variant(&first_experiment, &unit_value),
+                BucketAssignment::OutsideBaseMod { .. }
+            );
+            let in_second = !matches!(
+                assign_variant(&second_experiment, &unit_value),
+                BucketAssignment::OutsideBaseMod { .. }
+            );
+            assert!(in_first ^ in_second);
+        }
+    }
+}
@@ -1,3 +1,30 @@
+mod clock;
+mod context_expression;
+pub mod core_qualification_api;
 mod core_qualification_dto;
+mod core_qualification_error;
+mod core_qualification_exposure;
 mod core_qualification_lib;
-mod ep_dto;
+mod core_qualification_trace;
+pub mod ep_dto;
+#[cfg(feature = "serde")]
+pub mod ep_loader;
+pub mod ep_validation;
+mod hashing;
+mod spectrum;
+
+pub use clock::{Clock, ManualClock, SystemClock};
+pub use context_expression::ParseError;
+pub use core_qualification_api::{
+    AssignedVariant, ExperimentResult, QualificationRequest, QualificationResponse, VariantResult,
+};
+pub use core_qualification_dto::{ErrorMode, QualificationResult, QualificationResultType};
+pub use core_qualification_error::QualificationError;
+#[cfg(feature = "serde")]
+pub use core_qualification_exposure::NdjsonFileExposureSink;
+pub use core_qualification_exposure::{
+    BatchingExposureSink, ExposureEvent, ExposureSink, InMemoryExposureSink,
+};
+pub use core_qualification_lib::QualificationEngine;
+pub use core_qualification_trace::{DecisionTrace, TraceStep, TraceStepKind, VariantChange};
+pub use spectrum::Spectrum;
@@ -0,0 +1,366 @@
+use feature4qi::ep_loader::load_experiments_from_file;
+use feature4qi::{
+    ErrorMode, ManualClock, QualificationEngine, QualificationRequest, QualificationResponse,
+};
+use std::collections::HashMap;
+use std::process::ExitCode;
+use std::sync::Arc;
+
+const USAGE: &str = "\
+Evaluate a user against an experiment file and explain every variant result
+
+Usage: feature4qi <EXPERIMENT_FILE> [OPTIONS]
+
+Options:
+  -c, --context KEY=VALUE     Context value, repeatable (e.g. -c SITEID=77 -c UID=1038812)
+  -o, --opt-in DISPLAY_ID     Opt-in variant display id, repeatable
+  -j, --json JSON             Context and opt-ins as one JSON object:
+                              {\"context\": {\"SITEID\": 77}, \"opt_in\": [\"0aX0\"]}
+      --isolate-errors        Report failing experiments as Error instead of aborting
+      --max-qualified N       Qualify at most N experiments
+      --now MILLIS            Evaluate schedules at this time (milliseconds since the Unix epoch)
+      --trace                 Print every Phase and Mapper decision after the results
+      --trace-json            Same as --trace, as JSON
+  -h, --help                  Print this help
+
+The experiment file is JSON, or YAML / TOML when built with the yaml / toml features.";
+
+// Command line of one evaluation
+#[derive(Debug, Default, PartialEq)]
+struct Options {
+    experiment_file: String,
+    context: HashMap<String, String>,
+    opt_in_variant_display_ids: Vec<String>,
+    error_mode: ErrorMode,
+    max_qualified_experiments: Option<usize>,
+    now_millis: Option<u64>,
+    trace: Option<TraceFormat>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TraceFormat {
+    Text,
+    Json,
+}
+
+fn main() -> ExitCode {
+    let options = match parse_args(std::env::args().skip(1)) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Err(message) => {
+            eprintln!("error: {}\n\n{}", message, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    let experiments = match load_experiments_from_file(&options.experiment_file) {
+        Ok(experiments) => experiments,
+        Err(config_error) => {
+            eprintln!("error: {}", config_error);
+            return ExitCode::FAILURE;
+        }
+    };
+    let mut engine = match options.max_qualified_experiments {
+        Some(max_qualified_experiments) => {
+            QualificationEngine::with_max_qualified_experiments(max_qualified_experiments)
+        }
+        None => QualificationEngine::default(),
+    };
+    if let Some(now_millis) = options.now_millis {
+        engine = engine.with_clock(Arc::new(ManualClock::new(now_millis)));
+    }
+    let response = engine.evaluate(QualificationRequest {
+        experiments,
+        context: options.context,
+        opt_in_variant_display_ids: options.opt_in_variant_display_ids,
+        error_mode: options.error_mode,
+        trace: options.trace.is_some(),
+    });
+    print!("{}", render(&response));
+    if let Some(trace) = &response.trace {
+        match options.trace {
+            Some(TraceFormat::Json) => println!("\n{}", trace.to_json()),
+            _ => print!("\nDecision trace\n{}", trace),
+        }
+    }
+    if response.error.is_some() {
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
+
+// None when help was requested
+fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Option<Options>, String> {
+    let mut options = Options::default();
+    let mut experiment_file = None;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value_of = |flag: &str| {
+            args.next()
+                .ok_or_else(|| format!("{} expects a value", flag))
+        };
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "-c" | "--context" => {
+                let value = value_of(&arg)?;
+                let (key, value) = value
+                    .split_once('=')
+                    .ok_or_else(|| format!("{} expects KEY=VALUE, found {}", arg, value))?;
+                options.context.insert(key.to_string(), value.to_string());
+            }
+            "-o" | "--opt-in" => options.opt_in_variant_display_ids.push(value_of(&arg)?),
+            "-j" | "--json" => merge_json(&mut options, &value_of(&arg)?)?,
+            "--isolate-errors" => options.error_mode = ErrorMode::IsolateExperiment,
+            "--trace" => options.trace = Some(TraceFormat::Text),
+            "--trace-json" => options.trace = Some(TraceFormat::Json),
+            "--max-qualified" => {
+                let value = value_of(&arg)?;
+                options.max_qualified_experiments = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("{} expects a number, found {}", arg, value))?,
+                );
+            }
+            "--now" => {
+                let value = value_of(&arg)?;
+                options.now_millis = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("{} expects a number, found {}", arg, value))?,
+                );
+            }
+            flag if flag.starts_with('-') => return Err(format!("unknown option {}", flag)),
+            _ if experiment_file.is_none() => experiment_file = Some(arg),
+            _ => return Err(format!("unexpected argument {}", arg)),
+        }
+    }
+    options.experiment_file = experiment_file.ok_or("missing EXPERIMENT_FILE")?;
+    Ok(Some(options))
+}
+
+// Context values may be JSON strings, numbers or booleans; they are compared as text
+fn merge_json(options: &mut Options, json: &str) -> Result<(), String> {
+    let invalid = |message: String| format!("invalid --json: {}", message);
+    let value: serde_json::Value =
+        serde_json::from_str(json).map_err(|error| invalid(error.to_string()))?;
+    let object = value
+        .as_object()
+        .ok_or_else(|| invalid("expected an object".to_string()))?;
+    for (key, field) in object {
+        match (key.as_str(), field) {
+            ("context", serde_json::Value::Object(context)) => {
+                for (context_key, context_value) in context {
+                    let context_value = match context_value {
+                        serde_json::Value::String(text) => text.clone(),
+                        serde_json::Value::Number(_) | serde_json::Value::Bool(_) => {
+                            context_value.to_string()
+                        }
+                        _ => return Err(invalid(format!("unsupported value of {}", context_key))),
+                    };
+                    options.context.insert(context_key.clone(), context_value);
+                }
+            }
+            ("opt_in", serde_json::Value::Array(display_ids)) => {
+                for display_id in display_ids {
+                    let display_id = display_id
+                        .as_str()
+                        .ok_or_else(|| invalid("opt_in expects display id strings".to_string()))?;
+                    options
+                        .opt_in_variant_display_ids
+                        .push(display_id.to_string());
+                }
+            }
+            _ => return Err(invalid(format!("unexpected field {}", key))),
+        }
+    }
+    Ok(())
+}
+
+// One block per experiment: the assigned variant, then every variant result with its reason
+fn render(response: &QualificationResponse) -> String {
+    let mut output = String::new();
+    if let Some(qualification_error) = &response.error {
+        output += &format!(
+            "Request aborted (error {}): {}\n",
+            qualification_error.code(),
+            qualification_error
+        );
+    }
+    for experiment_result in &response.experiment_results {
+        let assigned = match &experiment_result.assigned_variant {
+            Some(assigned_variant) => format!(
+                "{} ({}, {}) = {}",
+                assigned_variant.name,
+                assigned_variant.variant_id,
+                assigned_variant.variant_display_id,
+                assigned_variant.value
+            ),
+            None => "no variant".to_string(),
+        };
+        if !output.is_empty() {
+            output.push('\n');
+        }
+        output += &format!(
+            "Experiment {} {}: {}\n",
+            experiment_result.experiment_id, experiment_result.name, assigned
+        );
+        let rows: Vec<[String; 4]> = experiment_result
+            .variant_results
+            .iter()
+            .map(|variant_result| {
+                let qualification_result = &variant_result.qualification_result;
+                [
+                    variant_result.variant_id.to_string(),
+                    variant_result.name.clone(),
+                    format!("{:?}", qualification_result.qualification_result_type),
+                    qualification_result.qualification_result_reason.clone(),
+                ]
+            })
+            .collect();
+        output += &table(["VARIANT", "NAME", "RESULT", "REASON"], &rows);
+    }
+    let mut opt_in_results: Vec<_> = response.opt_in_results.iter().collect();
+    opt_in_results.sort_by_key(|(display_id, _)| display_id.as_str());
+    if !opt_in_results.is_empty() {
+        output += "\nOpt-ins\n";
+        let rows: Vec<[String; 3]> = opt_in_results
+            .into_iter()
+            .map(|(display_id, qualification_result)| {
+                [
+                    display_id.clone(),
+                    format!("{:?}", qualification_result.qualification_result_type),
+                    qualification_result.qualification_result_reason.clone(),
+                ]
+            })
+            .collect();
+        output += &table(["DISPLAY_ID", "RESULT", "REASON"], &rows);
+    }
+    output
+}
+
+// Left aligned columns separated by two spaces, indented under their heading
+fn table<const N: usize>(header: [&str; N], rows: &[[String; N]]) -> String {
+    let mut widths = header.map(str::len);
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let line = |cells: [&str; N]| {
+        let cells: Vec<_> = cells
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{:width$}", cell, width = width))
+            .collect();
+        format!("  {}\n", cells.join("  ").trim_end())
+    };
+    let mut output = line(header);
+    for row in rows {
+        output += &line(row.each_ref().map(String::as_str));
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use feature4qi::QualificationResultType;
+    use feature4qi::{AssignedVariant, ExperimentResult, QualificationResult, VariantResult};
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn parse_flags_and_json() {
+        let options = parse_args(args(&[
+            "experiments.json",
+            "-c",
+            "SITEID=77",
+            "--opt-in",
+            "0aX0",
+            "--json",
+            r#"{"context": {"CHANNELID": 1, "F90D": "TRUE"}, "opt_in": ["0aX1"]}"#,
+            "--isolate-errors",
+            "--trace",
+            "--max-qualified",
+            "3",
+            "--now",
+            "1700000000000",
+        ]))
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            options,
+            Options {
+                experiment_file: "experiments.json".to_string(),
+                context: HashMap::from([
+                    ("SITEID".to_string(), "77".to_string()),
+                    ("CHANNELID".to_string(), "1".to_string()),
+                    ("F90D".to_string(), "TRUE".to_string()),
+                ]),
+                opt_in_variant_display_ids: vec!["0aX0".to_string(), "0aX1".to_string()],
+                error_mode: ErrorMode::IsolateExperiment,
+                max_qualified_experiments: Some(3),
+                now_millis: Some(1_700_000_000_000),
+                trace: Some(TraceFormat::Text),
+            }
+        );
+        assert_eq!(parse_args(args(&["--help"])), Ok(None));
+        assert_eq!(
+            parse_args(args(&["experiments.json", "-c", "SITEID"])),
+            Err("-c expects KEY=VALUE, found SITEID".to_string())
+        );
+        assert_eq!(
+            parse_args(args(&["-c", "SITEID=77"])),
+            Err("missing EXPERIMENT_FILE".to_string())
+        );
+        assert!(parse_args(args(&["experiments.json", "-j", "[]"])).is_err());
+    }
+
+    #[test]
+    fn render_explains_every_variant() {
+        let response = QualificationResponse {
+            experiment_results: vec![ExperimentResult {
+                experiment_id: 65536,
+                name: "Color Experiment".to_string(),
+                assigned_variant: Some(AssignedVariant {
+                    variant_id: 1024,
+                    variant_display_id: "0aX0".to_string(),
+                    name: "Red Variant".to_string(),
+                    value: "#FF0000".to_string(),
+                }),
+                variant_results: vec![
+                    VariantResult {
+                        variant_id: 1024,
+                        name: "Red Variant".to_string(),
+                        qualification_result: QualificationResult::new(
+                            QualificationResultType::Qualified,
+                            "whitelisted uid",
+                        ),
+                    },
+                    VariantResult {
+                        variant_id: 1025,
+                        name: "Blue Variant".to_string(),
+                        qualification_result: QualificationResult::new(
+                            QualificationResultType::NotQualified,
+                            "Variant 1024 assigned",
+                        ),
+                    },
+                ],
+            }],
+            ..Default::default()
+        };
+        assert_eq!(
+            render(&response),
+            "\
+Experiment 65536 Color Experiment: Red Variant (1024, 0aX0) = #FF0000
+  VARIANT  NAME          RESULT        REASON
+  1024     Red Variant   Qualified     whitelisted uid
+  1025     Blue Variant  NotQualified  Variant 1024 assigned
+"
+        );
+    }
+}
@@ -0,0 +1,177 @@
+use std::fmt;
+use std::str::FromStr;
+
+const WORD_BITS: usize = u64::BITS as usize;
+
+// Allocation of traffic buckets, one bit per bucket, written as a string of '0' / '1' characters
+// where bucket 0 is the leftmost character. The number of buckets is set when the spectrum is
+// built and bits past it are always clear.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct Spectrum {
+    len: usize,
+    words: Box<[u64]>,
+}
+
+impl Spectrum {
+    // Spectrum of len buckets, none of them allocated
+    pub fn empty(len: usize) -> Self {
+        Spectrum {
+            len,
+            words: vec![0; len.div_ceil(WORD_BITS)].into_boxed_slice(),
+        }
+    }
+
+    // Spectrum of len buckets, all of them allocated
+    pub fn full(len: usize) -> Self {
+        Spectrum::empty(len).complement()
+    }
+
+    // Number of buckets
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn contains(&self, bucket: usize) -> bool {
+        bucket < self.len && self.words[bucket / WORD_BITS] & (1 << (bucket % WORD_BITS)) != 0
+    }
+
+    // Allocate a bucket; buckets past len are ignored
+    pub fn insert(&mut self, bucket: usize) {
+        if bucket < self.len {
+            self.words[bucket / WORD_BITS] |= 1 << (bucket % WORD_BITS);
+        }
+    }
+
+    // Number of allocated buckets
+    pub fn count_ones(&self) -> usize {
+        self.words
+            .iter()
+            .map(|word| word.count_ones() as usize)
+            .sum()
+    }
+
+    // Share of the buckets allocated, from 0 to 100
+    pub fn percent(&self) -> f64 {
+        if self.len == 0 {
+            return 0.0;
+        }
+        self.count_ones() as f64 * 100.0 / self.len as f64
+    }
+
+    // Allocated buckets in increasing order
+    pub fn buckets(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.len).filter(|bucket| self.contains(*bucket))
+    }
+
+    // Buckets allocated in either spectrum, over the buckets of self
+    pub fn union(&self, other: &Spectrum) -> Spectrum {
+        self.combine(other, |word, other_word| word | other_word)
+    }
+
+    // Buckets allocated in both spectrums, over the buckets of self
+    pub fn intersection(&self, other: &Spectrum) -> Spectrum {
+        self.combine(other, |word, other_word| word & other_word)
+    }
+
+    // Buckets not allocated in self
+    pub fn complement(&self) -> Spectrum {
+        let mut complement = self.clone();
+        complement.words.iter_mut().for_each(|word| *word = !*word);
+        complement.clear_unused_bits();
+        complement
+    }
+
+    fn combine(&self, other: &Spectrum, operation: impl Fn(u64, u64) -> u64) -> Spectrum {
+        let mut combined = self.clone();
+        for (index, word) in combined.words.iter_mut().enumerate() {
+            *word = operation(*word, other.words.get(index).copied().unwrap_or(0));
+        }
+        combined.clear_unused_bits();
+        combined
+    }
+
+    fn clear_unused_bits(&mut self) {
+        let used_bits = self.len % WORD_BITS;
+        if let Some(last_word) = self.words.last_mut()
+            && used_bits != 0
+        {
+            *last_word &= (1 << used_bits) - 1;
+        }
+    }
+}
+
+impl FromStr for Spectrum {
+    type Err = String;
+
+    fn from_str(spectrum: &str) -> Result<Self, Self::Err> {
+        let mut parsed = Spectrum::empty(spectrum.chars().count());
+        for (bucket, c) in spectrum.chars().enumerate() {
+            match c {
+                '0' => {}
+                '1' => parsed.insert(bucket),
+                _ => {
+                    return Err(format!(
+                        "spectrum must only contain '0' and '1', found '{}' at bucket {}",
+                        c, bucket
+                    ));
+                }
+            }
+        }
+        Ok(parsed)
+    }
+}
+
+impl fmt::Display for Spectrum {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let spectrum: String = (0..self.len)
+            .map(|bucket| if self.contains(bucket) { '1' } else { '0' })
+            .collect();
+        f.write_str(&spectrum)
+    }
+}
+
+impl fmt::Debug for Spectrum {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Spectrum(\"{}\")", self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_and_format() {
+        let text = "1".repeat(50) + &"0".repeat(49) + "1";
+        let spectrum: Spectrum = text.parse().unwrap();
+        assert_eq!(spectrum.len(), 100);
+        assert!(spectrum.contains(0) && spectrum.contains(49) && spectrum.contains(99));
+        assert!(!spectrum.contains(50) && !spectrum.contains(100));
+        assert_eq!(spectrum.count_ones(), 51);
+        assert_eq!(spectrum.percent(), 51.0);
+        assert_eq!(spectrum.to_string(), text);
+        assert_eq!(
+            "0012".parse::<Spectrum>(),
+            Err("spectrum must only contain '0' and '1', found '2' at bucket 3".to_string())
+        );
+    }
+
+    #[test]
+    fn set_operations() {
+        let first: Spectrum = ("1".repeat(60) + &"0".repeat(40)).parse().unwrap();
+        let second: Spectrum = ("0".repeat(40) + &"1".repeat(60)).parse().unwrap();
+        assert_eq!(first.union(&second), Spectrum::full(100));
+        assert_eq!(
+            first.intersection(&second).buckets().collect::<Vec<_>>(),
+            (40..60).collect::<Vec<_>>()
+        );
+        assert_eq!(first.complement().count_ones(), 40);
+        assert_eq!(first.complement().complement(), first);
+        assert_eq!(Spectrum::full(100).count_ones(), 100);
+        assert_eq!(Spectrum::empty(100).percent(), 0.0);
+    }
+}